@@ -0,0 +1,39 @@
+// Copyright (c) The StackClass Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Optional trace of what the tester does.
+//!
+//! When `STACKCLASS_DEBUG=1`, program resolution, fixture construction and
+//! every executed instruction are written to stderr, so a failing run can be
+//! reconstructed from its output alone.
+
+use std::sync::OnceLock;
+
+/// Environment variable enabling the debug trace.
+pub const DEBUG_ENV: &str = "STACKCLASS_DEBUG";
+
+/// Whether the debug trace is enabled for this process.
+pub fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| std::env::var(DEBUG_ENV).is_ok_and(|value| value == "1"))
+}
+
+/// Write a line to the debug trace, if enabled.
+///
+/// Takes `format_args!` so nothing is formatted when the trace is off.
+pub fn log(args: std::fmt::Arguments<'_>) {
+    if enabled() {
+        eprintln!("[debug] {}", args);
+    }
+}
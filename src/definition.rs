@@ -14,81 +14,106 @@
 
 use std::sync::Arc;
 
-use tester::{Case, Definition};
+use tester::{Case, CaseError, Definition, Harness};
 
-use crate::stages::{
-    base::*,
-    extensions::{
-        cpi::*, deployment::*, error::*, make_offer::*, offer::*, pda::*, security::*,
-        take_offer::*, testing::*, vault::*,
+use crate::{
+    report,
+    stages::{
+        base::*,
+        extensions::{
+            cpi::*, deployment::*, error::*, make_offer::*, offer::*, pda::*, security::*,
+            take_offer::*, testing::*, vault::*,
+        },
     },
 };
 
+/// Environment variable overriding the executable the harness runs.
+pub const EXECUTABLE_NAME_ENV: &str = "STACKCLASS_EXECUTABLE_NAME";
+
+/// Executable run when [`EXECUTABLE_NAME_ENV`] is not set.
+const DEFAULT_EXECUTABLE_NAME: &str = "your_program.sh";
+
+/// Wrap a stage so its outcome is also written to the optional JSON report.
+fn case(slug: &'static str, test: fn(&Harness) -> Result<(), CaseError>) -> Case {
+    Case::new(slug, Arc::new(move |harness: &Harness| report::record(slug, || test(harness))))
+}
+
+/// Every stage, in course order, as `(slug, test)`.
+const STAGES: &[(&str, fn(&Harness) -> Result<(), CaseError>)] = &[
+    // Base Stages (7 stages)
+    ("be1", be1::test_env_setup),
+    ("rs2", rs2::test_rust_basics),
+    ("sm3", sm3::test_solana_model),
+    ("at4", at4::test_anchor_try),
+    ("st5", st5::test_spl_token_basics),
+    ("cp6", cp6::test_cpi_transfer),
+    ("tt7", tt7::test_token_transfer),
+    // Extension Modules (9 modules × 4 stages = 36 cases)
+    // PDA Module
+    ("pa1", pa1::test_pda_concept),
+    ("pa2", pa2::test_pda_derivation),
+    ("pa3", pa3::test_pda_bump_seeds),
+    ("pa4", pa4::test_pda_practice),
+    // Vault Module
+    ("va1", va1::test_vault_intro),
+    ("va2", va2::test_vault_creation),
+    ("va3", va3::test_vault_security),
+    ("va4", va4::test_vault_practice),
+    // Offer Module
+    ("of1", of1::test_offer_data_structure),
+    ("of2", of2::test_offer_validation),
+    ("of3", of3::test_offer_pda),
+    ("of4", of4::test_offer_practice),
+    // Make Offer Module
+    ("mo1", mo1::test_make_offer_overview),
+    ("mo2", mo2::test_deposit_tokens),
+    ("mo3", mo3::test_save_offer),
+    ("mo4", mo4::test_make_offer_practice),
+    // Take Offer Module
+    ("to1", to1::test_take_offer_overview),
+    ("to2", to2::test_receive_tokens),
+    ("to3", to3::test_withdraw_vault),
+    ("to4", to4::test_take_offer_practice),
+    // Security Module
+    ("se1", se1::test_common_vulnerabilities),
+    ("se2", se2::test_reentrancy_protection),
+    ("se3", se3::test_account_validation),
+    ("se4", se4::test_security_practice),
+    // CPI Module
+    ("cp1", cp1::test_cpi_concept),
+    ("cp2", cp2::test_transfer_checked),
+    ("cp3", cp3::test_cpi_signer),
+    ("cp4", cp4::test_cpi_practice),
+    // Error Module
+    ("er1", er1::test_error_basics),
+    ("er2", er2::test_custom_errors),
+    ("er3", er3::test_error_messages),
+    ("er4", er4::test_error_practice),
+    // Testing Module
+    ("te1", te1::test_rust_test_basics),
+    ("te2", te2::test_anchor_test_attribute),
+    ("te3", te3::test_testing_setup_teardown),
+    ("te4", te4::test_comprehensive_tests),
+    // Deployment Module
+    ("de1", de1::test_local_testing),
+    ("de2", de2::test_devnet_deploy),
+    ("de3", de3::test_mainnet_considerations),
+    ("de4", de4::test_deployment_practice),
+];
+
 pub fn build() -> Definition {
     Definition {
-        executable_name: "your_program.sh".to_string(),
+        executable_name: std::env::var(EXECUTABLE_NAME_ENV)
+            .unwrap_or_else(|_| DEFAULT_EXECUTABLE_NAME.to_string()),
         legacy_executable_name: None,
-        cases: vec![
-            // Base Stages (7 stages)
-            Case::new("be1", Arc::new(be1::test_env_setup)),
-            Case::new("rs2", Arc::new(rs2::test_rust_basics)),
-            Case::new("sm3", Arc::new(sm3::test_solana_model)),
-            Case::new("at4", Arc::new(at4::test_anchor_try)),
-            Case::new("st5", Arc::new(st5::test_spl_token_basics)),
-            Case::new("cp6", Arc::new(cp6::test_cpi_transfer)),
-            Case::new("tt7", Arc::new(tt7::test_token_transfer)),
-            // Extension Modules (9 modules × 4 stages = 36 cases)
-            // PDA Module
-            Case::new("pa1", Arc::new(pa1::test_pda_concept)),
-            Case::new("pa2", Arc::new(pa2::test_pda_derivation)),
-            Case::new("pa3", Arc::new(pa3::test_pda_bump_seeds)),
-            Case::new("pa4", Arc::new(pa4::test_pda_practice)),
-            // Vault Module
-            Case::new("va1", Arc::new(va1::test_vault_intro)),
-            Case::new("va2", Arc::new(va2::test_vault_creation)),
-            Case::new("va3", Arc::new(va3::test_vault_security)),
-            Case::new("va4", Arc::new(va4::test_vault_practice)),
-            // Offer Module
-            Case::new("of1", Arc::new(of1::test_offer_data_structure)),
-            Case::new("of2", Arc::new(of2::test_offer_validation)),
-            Case::new("of3", Arc::new(of3::test_offer_pda)),
-            Case::new("of4", Arc::new(of4::test_offer_practice)),
-            // Make Offer Module
-            Case::new("mo1", Arc::new(mo1::test_make_offer_overview)),
-            Case::new("mo2", Arc::new(mo2::test_deposit_tokens)),
-            Case::new("mo3", Arc::new(mo3::test_save_offer)),
-            Case::new("mo4", Arc::new(mo4::test_make_offer_practice)),
-            // Take Offer Module
-            Case::new("to1", Arc::new(to1::test_take_offer_overview)),
-            Case::new("to2", Arc::new(to2::test_receive_tokens)),
-            Case::new("to3", Arc::new(to3::test_withdraw_vault)),
-            Case::new("to4", Arc::new(to4::test_take_offer_practice)),
-            // Security Module
-            Case::new("se1", Arc::new(se1::test_common_vulnerabilities)),
-            Case::new("se2", Arc::new(se2::test_reentrancy_protection)),
-            Case::new("se3", Arc::new(se3::test_account_validation)),
-            Case::new("se4", Arc::new(se4::test_security_practice)),
-            // CPI Module
-            Case::new("cp1", Arc::new(cp1::test_cpi_concept)),
-            Case::new("cp2", Arc::new(cp2::test_transfer_checked)),
-            Case::new("cp3", Arc::new(cp3::test_cpi_signer)),
-            Case::new("cp4", Arc::new(cp4::test_cpi_practice)),
-            // Error Module
-            Case::new("er1", Arc::new(er1::test_error_basics)),
-            Case::new("er2", Arc::new(er2::test_custom_errors)),
-            Case::new("er3", Arc::new(er3::test_error_messages)),
-            Case::new("er4", Arc::new(er4::test_error_practice)),
-            // Testing Module
-            Case::new("te1", Arc::new(te1::test_rust_test_basics)),
-            Case::new("te2", Arc::new(te2::test_anchor_test_attribute)),
-            Case::new("te3", Arc::new(te3::test_testing_setup_teardown)),
-            Case::new("te4", Arc::new(te4::test_comprehensive_tests)),
-            // Deployment Module
-            Case::new("de1", Arc::new(de1::test_local_testing)),
-            Case::new("de2", Arc::new(de2::test_devnet_deploy)),
-            Case::new("de3", Arc::new(de3::test_mainnet_considerations)),
-            Case::new("de4", Arc::new(de4::test_deployment_practice)),
-        ],
+        cases: STAGES.iter().map(|&(slug, test)| case(slug, test)).collect(),
         ..Default::default()
     }
 }
+
+/// List the slugs of every registered case, in the order [`build`] runs
+/// them, without building the cases.
+#[allow(dead_code)]
+pub fn case_ids() -> Vec<&'static str> {
+    STAGES.iter().map(|&(slug, _)| slug).collect()
+}
@@ -15,32 +15,58 @@
 //! Helper functions for testing the swap program.
 
 use crate::mollusk::{
-    ProgramLoadError, TestContextError, init_test_context, load_swap_program, load_swap_program_id,
+    AccountSnapshot, ProgramLoadError, ResultCheck, TestContextError,
+    extensions::{ACCOUNT_TYPE_ACCOUNT, ACCOUNT_TYPE_MINT, with_extensions},
+    hex, init_test_context_with_programs, load_idl_instruction_accounts,
+    load_idl_instruction_discriminator, load_swap_program, load_swap_program_id,
 };
-use mollusk_svm::{program::keyed_account_for_system_program, result::Check};
-use mollusk_svm_programs_token::{associated_token, token};
+use mollusk_svm::program::keyed_account_for_system_program;
+use mollusk_svm_programs_token::{associated_token, token, token2022};
 use sha2::{Digest, Sha256};
 use solana_account::Account;
 use solana_instruction::{AccountMeta, Instruction};
 use solana_program_option::COption;
 use solana_pubkey::Pubkey;
+use solana_rent::Rent;
 use spl_associated_token_account_interface::address::get_associated_token_address_with_program_id;
-use spl_token_interface::state::{Account as TokenAccount, AccountState, Mint};
-use std::{convert::TryInto, path::Path};
+use spl_token_interface::{
+    error::TokenError,
+    state::{Account as TokenAccount, AccountState, Mint},
+};
+use std::{
+    collections::BTreeMap,
+    convert::TryInto,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 /// Get the repository directory from environment variables.
 ///
 /// This function reads the `STACKCLASS_REPOSITORY_DIR` environment variable
-/// and returns it as a Path.
+/// and canonicalizes it, resolving relative components and symlinks, so every
+/// later lookup sees the same absolute path. The outcome is resolved once per
+/// process and cached, so every stage shares the same path and error.
 ///
 /// # Returns
 ///
-/// * `Ok(PathBuf)` - The repository directory path
-/// * `Err(ProgramLoadError)` - If the environment variable is not set
-pub fn get_repo_dir() -> Result<std::path::PathBuf, ProgramLoadError> {
-    std::env::var("STACKCLASS_REPOSITORY_DIR")
-        .map_err(|_| ProgramLoadError::RepoNotFound(std::path::PathBuf::from("Not set")))
-        .map(std::path::PathBuf::from)
+/// * `Ok(PathBuf)` - The canonical repository directory path
+/// * `Err(ProgramLoadError)` - If the environment variable is not set or the directory does not
+///   exist
+pub fn get_repo_dir() -> Result<PathBuf, ProgramLoadError> {
+    static REPO_DIR: OnceLock<Result<PathBuf, PathBuf>> = OnceLock::new();
+
+    REPO_DIR
+        .get_or_init(|| match std::env::var("STACKCLASS_REPOSITORY_DIR") {
+            Ok(dir) => canonical_repo_dir(&dir),
+            Err(_) => Err(PathBuf::from("Not set")),
+        })
+        .clone()
+        .map_err(ProgramLoadError::RepoNotFound)
+}
+
+/// Canonicalize `dir`, or hand it back unchanged if it is not a directory.
+fn canonical_repo_dir(dir: &str) -> Result<PathBuf, PathBuf> {
+    std::fs::canonicalize(dir).ok().filter(|path| path.is_dir()).ok_or_else(|| PathBuf::from(dir))
 }
 
 /// Create a test error message for reporting to the user.
@@ -105,10 +131,10 @@ pub fn create_pda(seeds: &[&[u8]], program_id: &Pubkey) -> (Pubkey, u8) {
 ///
 /// # Returns
 ///
-/// * `Check` - A success check
+/// * `ResultCheck` - A success check
 #[allow(dead_code)]
-pub fn success_check() -> Check<'static> {
-    Check::success()
+pub fn success_check() -> ResultCheck<'static> {
+    ResultCheck::Success
 }
 
 /// Create a check for account lamports.
@@ -120,10 +146,10 @@ pub fn success_check() -> Check<'static> {
 ///
 /// # Returns
 ///
-/// * `Check` - A lamports check
+/// * `ResultCheck` - A lamports check
 #[allow(dead_code)]
-pub fn lamports_check(pubkey: &Pubkey, expected_lamports: u64) -> Check<'_> {
-    Check::account(pubkey).lamports(expected_lamports).build()
+pub fn lamports_check(pubkey: &Pubkey, expected_lamports: u64) -> ResultCheck<'_> {
+    ResultCheck::Lamports(pubkey, expected_lamports)
 }
 
 /// Create a check for account data.
@@ -135,10 +161,10 @@ pub fn lamports_check(pubkey: &Pubkey, expected_lamports: u64) -> Check<'_> {
 ///
 /// # Returns
 ///
-/// * `Check` - A data check
+/// * `ResultCheck` - A data check
 #[allow(dead_code)]
-pub fn data_check<'a>(pubkey: &'a Pubkey, expected_data: &'a [u8]) -> Check<'a> {
-    Check::account(pubkey).data(expected_data).build()
+pub fn data_check<'a>(pubkey: &'a Pubkey, expected_data: &'a [u8]) -> ResultCheck<'a> {
+    ResultCheck::Data(pubkey, expected_data)
 }
 
 /// Create a check for account owner.
@@ -150,10 +176,10 @@ pub fn data_check<'a>(pubkey: &'a Pubkey, expected_data: &'a [u8]) -> Check<'a>
 ///
 /// # Returns
 ///
-/// * `Check` - An owner check
+/// * `ResultCheck` - An owner check
 #[allow(dead_code)]
-pub fn owner_check<'a>(pubkey: &'a Pubkey, expected_owner: &'a Pubkey) -> Check<'a> {
-    Check::account(pubkey).owner(expected_owner).build()
+pub fn owner_check<'a>(pubkey: &'a Pubkey, expected_owner: &'a Pubkey) -> ResultCheck<'a> {
+    ResultCheck::Owner(pubkey, expected_owner)
 }
 
 /// Create a check for account executability.
@@ -165,10 +191,10 @@ pub fn owner_check<'a>(pubkey: &'a Pubkey, expected_owner: &'a Pubkey) -> Check<
 ///
 /// # Returns
 ///
-/// * `Check` - An executable check
+/// * `ResultCheck` - An executable check
 #[allow(dead_code)]
-pub fn executable_check(pubkey: &Pubkey, expected_executable: bool) -> Check<'_> {
-    Check::account(pubkey).executable(expected_executable).build()
+pub fn executable_check(pubkey: &Pubkey, expected_executable: bool) -> ResultCheck<'_> {
+    ResultCheck::Executable(pubkey, expected_executable)
 }
 
 /// Convert a TestContextError to a tester::CaseError.
@@ -251,7 +277,66 @@ pub fn create_swap_instruction(
 const DEFAULT_OFFERED_AMOUNT: u64 = 1_000_000;
 const DEFAULT_WANTED_AMOUNT: u64 = 1_000_000;
 const DEFAULT_MINT_DECIMALS: u8 = 6;
+const UNUSUAL_MINT_DECIMALS: [(u8, u8); 2] = [(0, 9), (9, 0)];
+/// Mint A decimals used by the decimals-mismatch check, against the default
+/// of [`DEFAULT_MINT_DECIMALS`] a program might assume.
+const MISMATCHED_MINT_DECIMALS_A: u8 = 9;
+const DEFAULT_OFFER_ID: u64 = 1;
+/// Token A amount taken by the partial-fill check.
+const PARTIAL_FILL_AMOUNT: u64 = 400_000;
+
+/// Namespace Anchor uses for program instruction discriminators.
+pub const GLOBAL_NAMESPACE: &str = "global";
+/// Anchor discriminator preimage of the offer account, `account:<struct name>`.
+const OFFER_ACCOUNT_DISCRIMINATOR_NAME: &str = "account:Offer";
 const OFFER_SEED_PREFIX: &[u8] = b"offer";
+/// Swap instructions whose discriminators are looked up in the IDL.
+const SWAP_INSTRUCTIONS: [&str; 4] = ["make_offer", "take_offer", "refund_offer", "close_offer"];
+/// Offset of the stored bump in offer account data, after the discriminator,
+/// id, maker, both mints and the wanted amount.
+const OFFER_BUMP_OFFSET: usize = 8 + 8 + 32 + 32 + 32 + 8;
+
+/// Compute unit ceiling for a single `make_offer`.
+pub const MAKE_OFFER_COMPUTE_UNIT_LIMIT: u64 = 100_000;
+
+/// Transfer fee used by the transfer-fee check, in basis points.
+const TRANSFER_FEE_BASIS_POINTS: u16 = 100;
+const TRANSFER_FEE_MAXIMUM: u64 = 5_000;
+const MAX_FEE_BASIS_POINTS: u128 = 10_000;
+
+// Token-2022 extension types; see `crate::mollusk::extensions` for the layout.
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+const EXTENSION_TRANSFER_FEE_AMOUNT: u16 = 2;
+
+/// Transfer fee charged by a Token-2022 mint through its `TransferFeeConfig`
+/// extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferFee {
+    pub basis_points: u16,
+    pub maximum_fee: u64,
+}
+
+impl TransferFee {
+    /// Fee withheld from a transfer of `amount`, rounded up and capped at the
+    /// maximum fee as Token-2022 does.
+    pub fn fee_for(&self, amount: u64) -> u64 {
+        let fee =
+            (u128::from(amount) * u128::from(self.basis_points)).div_ceil(MAX_FEE_BASIS_POINTS);
+        fee.min(u128::from(self.maximum_fee)) as u64
+    }
+
+    /// Encode the `TransferFeeConfig` extension value with no authorities,
+    /// nothing withheld and the same fee for the older and newer epochs.
+    fn config_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0u8; 72];
+        for _ in 0..2 {
+            data.extend_from_slice(&0u64.to_le_bytes());
+            data.extend_from_slice(&self.maximum_fee.to_le_bytes());
+            data.extend_from_slice(&self.basis_points.to_le_bytes());
+        }
+        data
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct OfferData {
@@ -263,6 +348,47 @@ pub struct OfferData {
     pub bump: u8,
 }
 
+/// Token balances of the fixture's accounts, in base units.
+///
+/// Missing or closed accounts read as `0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalanceSnapshot {
+    pub maker_a: u64,
+    pub maker_b: u64,
+    pub taker_a: u64,
+    pub taker_b: u64,
+    pub vault_a: u64,
+}
+
+impl BalanceSnapshot {
+    /// Total mint A balance across the user accounts and the vault.
+    ///
+    /// Summed as `u128`, so balances inflated by a buggy program cannot
+    /// overflow.
+    pub fn total_a(&self) -> u128 {
+        u128::from(self.maker_a) + u128::from(self.taker_a) + u128::from(self.vault_a)
+    }
+
+    /// Total mint B balance across the user accounts; see
+    /// [`BalanceSnapshot::total_a`].
+    pub fn total_b(&self) -> u128 {
+        u128::from(self.maker_b) + u128::from(self.taker_b)
+    }
+}
+
+/// What a full `make_offer` + `take_offer` did to the fixture's accounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapOutcome {
+    /// Balances before `make_offer`.
+    pub before: BalanceSnapshot,
+    /// Balances after `take_offer`.
+    pub after: BalanceSnapshot,
+    /// Whether `take_offer` closed the offer account.
+    pub offer_closed: bool,
+    /// Whether `take_offer` closed the vault.
+    pub vault_closed: bool,
+}
+
 pub struct SwapFixture {
     context: crate::mollusk::SwapTestContext,
     program_id: Pubkey,
@@ -277,41 +403,237 @@ pub struct SwapFixture {
     pub offer_id: u64,
     pub offer: Pubkey,
     pub vault: Pubkey,
+    /// Every offer made through the fixture, keyed by offer id, as
+    /// `(offer, vault)`.
+    pub offers: BTreeMap<u64, (Pubkey, Pubkey)>,
+    /// Seed scheme used to derive offer PDAs.
+    pub offer_seeds: OfferSeeds,
+    /// Instruction discriminators read from the program's IDL, by name.
+    discriminators: BTreeMap<&'static str, Vec<u8>>,
+    /// Token program owning the mints and token accounts, SPL Token or
+    /// Token-2022. Every ATA, including the vault, is derived with this id.
     pub token_program: Pubkey,
+    /// The canonical Associated Token program, shared by both token programs.
     pub associated_token_program: Pubkey,
+    /// Offered amount, in mint A base units.
     pub offered_amount: u64,
+    /// Wanted amount, in mint B base units.
     pub wanted_amount: u64,
+    /// Transfer fee configured on mint A, if any.
+    pub transfer_fee_a: Option<TransferFee>,
     #[allow(dead_code)]
     pub decimals_a: u8,
+    #[allow(dead_code)]
+    pub decimals_b: u8,
 }
 
-impl SwapFixture {
-    pub fn new_default(repo_dir: &Path) -> Result<Self, TestContextError> {
-        Self::new_with_amounts(
-            repo_dir,
-            DEFAULT_OFFERED_AMOUNT,
-            DEFAULT_WANTED_AMOUNT,
-            DEFAULT_OFFERED_AMOUNT,
-            DEFAULT_WANTED_AMOUNT,
-            DEFAULT_MINT_DECIMALS,
-        )
+/// Builder for a [`SwapFixture`] with non-default amounts or mint settings.
+///
+/// All amounts are in the base units of their mint.
+#[derive(Debug, Clone)]
+pub struct SwapFixtureBuilder {
+    offered_amount: u64,
+    wanted_amount: u64,
+    maker_balance_a: u64,
+    taker_balance_b: u64,
+    decimals_a: u8,
+    decimals_b: u8,
+    token_program: Pubkey,
+    freeze_maker_token_account_a: bool,
+    transfer_fee_a: Option<TransferFee>,
+    rent: Option<Rent>,
+    omit_maker_token_account_b: bool,
+    same_mint: bool,
+    token_program_account: Option<(Pubkey, Account)>,
+    offer_seeds: OfferSeeds,
+}
+
+impl Default for SwapFixtureBuilder {
+    fn default() -> Self {
+        Self {
+            offered_amount: DEFAULT_OFFERED_AMOUNT,
+            wanted_amount: DEFAULT_WANTED_AMOUNT,
+            maker_balance_a: DEFAULT_OFFERED_AMOUNT,
+            taker_balance_b: DEFAULT_WANTED_AMOUNT,
+            decimals_a: DEFAULT_MINT_DECIMALS,
+            decimals_b: DEFAULT_MINT_DECIMALS,
+            token_program: token::ID,
+            freeze_maker_token_account_a: false,
+            transfer_fee_a: None,
+            rent: None,
+            omit_maker_token_account_b: false,
+            same_mint: false,
+            token_program_account: None,
+            offer_seeds,
+        }
     }
+}
 
-    pub fn new_with_amounts(
-        repo_dir: &Path,
-        offered_amount: u64,
-        wanted_amount: u64,
-        maker_balance_a: u64,
-        taker_balance_b: u64,
-        decimals: u8,
-    ) -> Result<Self, TestContextError> {
-        let mut context = init_test_context(repo_dir)?;
+impl SwapFixtureBuilder {
+    pub fn offered_amount(mut self, offered_amount: u64) -> Self {
+        self.offered_amount = offered_amount;
+        self
+    }
+
+    pub fn wanted_amount(mut self, wanted_amount: u64) -> Self {
+        self.wanted_amount = wanted_amount;
+        self
+    }
+
+    pub fn maker_balance_a(mut self, maker_balance_a: u64) -> Self {
+        self.maker_balance_a = maker_balance_a;
+        self
+    }
+
+    pub fn taker_balance_b(mut self, taker_balance_b: u64) -> Self {
+        self.taker_balance_b = taker_balance_b;
+        self
+    }
+
+    /// Set the decimals of both mints.
+    pub fn decimals(self, decimals: u8) -> Self {
+        self.decimals_a(decimals).decimals_b(decimals)
+    }
+
+    pub fn decimals_a(mut self, decimals_a: u8) -> Self {
+        self.decimals_a = decimals_a;
+        self
+    }
+
+    pub fn decimals_b(mut self, decimals_b: u8) -> Self {
+        self.decimals_b = decimals_b;
+        self
+    }
+
+    /// Set the token program that owns the mints and token accounts.
+    ///
+    /// Either the legacy SPL Token program or Token-2022.
+    #[allow(dead_code)]
+    pub fn token_program(mut self, token_program: Pubkey) -> Self {
+        self.token_program = token_program;
+        self
+    }
+
+    /// Derive offer PDAs with a different seed scheme, for tracks that seed
+    /// the offer with e.g. `[b"offer", id]` or include a mint.
+    #[allow(dead_code)]
+    pub fn offer_seeds(mut self, offer_seeds: OfferSeeds) -> Self {
+        self.offer_seeds = offer_seeds;
+        self
+    }
+
+    /// Use a custom token program instead of the bundled SPL Token or
+    /// Token-2022 build, e.g. a locally built `.so` read with
+    /// [`crate::mollusk::load_program_elf`].
+    ///
+    /// The account must be a loader-owned program account whose data is the
+    /// program ELF; it is registered with Mollusk under its owner as the
+    /// loader, and its id is used in every instruction and ATA derivation.
+    #[allow(dead_code)]
+    pub fn token_program_account(mut self, program_id: Pubkey, account: Account) -> Self {
+        self.token_program = program_id;
+        self.token_program_account = Some((program_id, account));
+        self
+    }
+
+    /// Freeze the maker's token A account, giving mint A the maker as its
+    /// freeze authority.
+    pub fn freeze_maker_token_account_a(mut self) -> Self {
+        self.freeze_maker_token_account_a = true;
+        self
+    }
+
+    /// Give mint A a Token-2022 transfer fee.
+    ///
+    /// Switches the fixture to Token-2022 and adds the `TransferFeeAmount`
+    /// extension to the token A accounts it creates. `basis_points` above
+    /// 10,000 (100%) is rejected by [`SwapFixtureBuilder::build`].
+    pub fn transfer_fee_a(mut self, basis_points: u16, maximum_fee: u64) -> Self {
+        self.transfer_fee_a = Some(TransferFee { basis_points, maximum_fee });
+        self.token_program = token2022::ID;
+        self
+    }
+
+    /// Run the fixture under custom rent parameters instead of the default.
+    ///
+    /// Mints and token accounts are funded against this rent.
+    pub fn rent(mut self, rent: Rent) -> Self {
+        self.rent = Some(rent);
+        self
+    }
+
+    /// Leave the maker's token B ATA uninitialized so `take_offer` has to
+    /// create it.
+    pub fn omit_maker_token_account_b(mut self) -> Self {
+        self.omit_maker_token_account_b = true;
+        self
+    }
+
+    /// Offer mint A in exchange for mint A itself.
+    ///
+    /// Both parties then hold a single ATA each, so `maker_token_account_a`
+    /// and `maker_token_account_b` are the same account, as are the taker's.
+    /// The maker's ATA starts with `maker_balance_a` and the taker's with
+    /// `taker_balance_b`, and mint B's decimals follow mint A's.
+    ///
+    /// A standard escrow has no constraint against this and is expected to
+    /// complete the swap; a program that rejects same-mint offers fails
+    /// `make_offer` with an `ExecutionError`. Checks should accept either, but
+    /// must not read [`BalanceSnapshot::total_a`] and
+    /// [`BalanceSnapshot::total_b`] together, since they count the shared
+    /// accounts twice.
+    #[allow(dead_code)]
+    pub fn same_mint(mut self) -> Self {
+        self.same_mint = true;
+        self
+    }
+
+    pub fn build(self, repo_dir: &Path) -> Result<SwapFixture, TestContextError> {
+        if let Some(fee) = self.transfer_fee_a &&
+            u128::from(fee.basis_points) > MAX_FEE_BASIS_POINTS
+        {
+            return Err(TestContextError::ValidationError(format!(
+                "transfer fee of {} basis points exceeds the maximum of {}",
+                fee.basis_points, MAX_FEE_BASIS_POINTS
+            )));
+        }
+        // With a shared mint, the mint's supply covers both users' balances
+        let supply_a = if self.same_mint {
+            self.maker_balance_a.checked_add(self.taker_balance_b).ok_or_else(|| {
+                TestContextError::ValidationError(format!(
+                    "shared mint supply overflows u64: maker holds {}, taker holds {}",
+                    self.maker_balance_a, self.taker_balance_b
+                ))
+            })?
+        } else {
+            self.maker_balance_a
+        };
+
+        let bundled_token_programs: &[Pubkey] = match self.token_program_account {
+            Some(_) => &[],
+            None => &[self.token_program],
+        };
+        let mut context = init_test_context_with_programs(repo_dir, bundled_token_programs)?;
         let program_id = context.program_id();
+        if let Some(rent) = self.rent {
+            context.set_rent(rent);
+        }
 
         let (system_program_id, system_program_account) = keyed_account_for_system_program();
         context.add_account(system_program_id, system_program_account);
 
-        let (token_program_id, token_program_account) = token::keyed_account();
+        let (token_program_id, token_program_account) = match self.token_program_account.clone() {
+            Some((program_id, account)) => {
+                context.mollusk_mut().add_program_with_elf_and_loader(
+                    &program_id,
+                    &account.data,
+                    &account.owner,
+                );
+                (program_id, account)
+            }
+            None if self.token_program == token2022::ID => token2022::keyed_account(),
+            None => token::keyed_account(),
+        };
         context.add_account(token_program_id, token_program_account);
 
         let (associated_program_id, associated_program_account) = associated_token::keyed_account();
@@ -321,25 +643,38 @@ impl SwapFixture {
         let taker = context.create_funded_account(1_000_000_000);
 
         let token_mint_a = Pubkey::new_unique();
-        let token_mint_b = Pubkey::new_unique();
+        let token_mint_b = if self.same_mint { token_mint_a } else { Pubkey::new_unique() };
+        let decimals_b = if self.same_mint { self.decimals_a } else { self.decimals_b };
 
         let mint_a = Mint {
             mint_authority: COption::Some(maker),
-            supply: maker_balance_a,
-            decimals,
+            supply: supply_a,
+            decimals: self.decimals_a,
             is_initialized: true,
-            freeze_authority: COption::None,
+            freeze_authority: if self.freeze_maker_token_account_a {
+                COption::Some(maker)
+            } else {
+                COption::None
+            },
         };
         let mint_b = Mint {
             mint_authority: COption::Some(taker),
-            supply: taker_balance_b,
-            decimals,
+            supply: self.taker_balance_b,
+            decimals: decimals_b,
             is_initialized: true,
             freeze_authority: COption::None,
         };
 
-        context.add_account(token_mint_a, token::create_account_for_mint(mint_a));
-        context.add_account(token_mint_b, token::create_account_for_mint(mint_b));
+        context.add_rent_exempt_account(
+            token_mint_a,
+            mint_account(mint_a, &token_program_id, self.transfer_fee_a)?,
+        );
+        if !self.same_mint {
+            context.add_rent_exempt_account(
+                token_mint_b,
+                mint_account(mint_b, &token_program_id, None)?,
+            );
+        }
 
         let maker_token_account_a =
             get_associated_token_address_with_program_id(&maker, &token_mint_a, &token_program_id);
@@ -350,74 +685,89 @@ impl SwapFixture {
         let taker_token_account_b =
             get_associated_token_address_with_program_id(&taker, &token_mint_b, &token_program_id);
 
-        context.add_account(
+        let maker_account_a_state = if self.freeze_maker_token_account_a {
+            AccountState::Frozen
+        } else {
+            AccountState::Initialized
+        };
+        let token_account_a = |owner, amount, state| {
+            let account = token_account(token_mint_a, owner, amount, state, &token_program_id);
+            match self.transfer_fee_a {
+                Some(_) => with_transfer_fee_amount(account),
+                None => Ok(account),
+            }
+        };
+        context.add_rent_exempt_account(
             maker_token_account_a,
-            token::create_account_for_token_account(TokenAccount {
-                mint: token_mint_a,
-                owner: maker,
-                amount: maker_balance_a,
-                delegate: COption::None,
-                state: AccountState::Initialized,
-                is_native: COption::None,
-                delegated_amount: 0,
-                close_authority: COption::None,
-            }),
+            token_account_a(maker, self.maker_balance_a, maker_account_a_state)?,
         );
-
-        context.add_account(
-            maker_token_account_b,
-            token::create_account_for_token_account(TokenAccount {
-                mint: token_mint_b,
-                owner: maker,
-                amount: 0,
-                delegate: COption::None,
-                state: AccountState::Initialized,
-                is_native: COption::None,
-                delegated_amount: 0,
-                close_authority: COption::None,
-            }),
-        );
-
-        context.add_account(
+        if self.same_mint {
+            // The maker's token B account is the token A account added above
+        } else if self.omit_maker_token_account_b {
+            context.add_account(maker_token_account_b, empty_system_account());
+        } else {
+            context.add_rent_exempt_account(
+                maker_token_account_b,
+                token_account(token_mint_b, maker, 0, AccountState::Initialized, &token_program_id),
+            );
+        }
+        context.add_rent_exempt_account(
             taker_token_account_a,
-            token::create_account_for_token_account(TokenAccount {
-                mint: token_mint_a,
-                owner: taker,
-                amount: 0,
-                delegate: COption::None,
-                state: AccountState::Initialized,
-                is_native: COption::None,
-                delegated_amount: 0,
-                close_authority: COption::None,
-            }),
-        );
-
-        context.add_account(
-            taker_token_account_b,
-            token::create_account_for_token_account(TokenAccount {
-                mint: token_mint_b,
-                owner: taker,
-                amount: taker_balance_b,
-                delegate: COption::None,
-                state: AccountState::Initialized,
-                is_native: COption::None,
-                delegated_amount: 0,
-                close_authority: COption::None,
-            }),
+            token_account_a(taker, 0, AccountState::Initialized)?,
         );
+        if self.same_mint {
+            context.add_rent_exempt_account(
+                taker_token_account_b,
+                token_account_a(taker, self.taker_balance_b, AccountState::Initialized)?,
+            );
+        } else {
+            context.add_rent_exempt_account(
+                taker_token_account_b,
+                token_account(
+                    token_mint_b,
+                    taker,
+                    self.taker_balance_b,
+                    AccountState::Initialized,
+                    &token_program_id,
+                ),
+            );
+        }
 
-        let offer_id: i32 = 1;
-        let (offer, _bump) = Pubkey::find_program_address(
-            &[OFFER_SEED_PREFIX, maker.as_ref(), &offer_id.to_le_bytes()],
-            &program_id,
-        );
+        let offer_id = DEFAULT_OFFER_ID;
+        let (offer, _bump) = find_offer_address(self.offer_seeds, &maker, offer_id, &program_id);
         let vault =
             get_associated_token_address_with_program_id(&offer, &token_mint_a, &token_program_id);
 
         context.add_account(offer, empty_system_account());
         context.add_account(vault, empty_system_account());
 
-        Ok(Self {
+        let offers = BTreeMap::from([(offer_id, (offer, vault))]);
+        crate::debug::log(format_args!(
+            "fixture: program {}, token program {}, maker {}, taker {}, mint A {}, mint B {}, \
+             maker ATAs {}/{}, taker ATAs {}/{}, offer {} (id {}), vault {}",
+            program_id,
+            token_program_id,
+            maker,
+            taker,
+            token_mint_a,
+            token_mint_b,
+            maker_token_account_a,
+            maker_token_account_b,
+            taker_token_account_a,
+            taker_token_account_b,
+            offer,
+            offer_id,
+            vault
+        ));
+        let discriminators = SWAP_INSTRUCTIONS
+            .iter()
+            .filter_map(|&name| {
+                load_idl_instruction_discriminator(repo_dir, name)
+                    .map(|discriminator| (name, discriminator))
+            })
+            .collect();
+
+        Ok(SwapFixture {
             context,
             program_id,
             maker,
@@ -428,19 +778,98 @@ impl SwapFixture {
             maker_token_account_b,
             taker_token_account_a,
             taker_token_account_b,
-            offer_id: offer_id.try_into().unwrap(),
+            offer_id,
             offer,
             vault,
+            offers,
+            offer_seeds: self.offer_seeds,
+            discriminators,
             token_program: token_program_id,
             associated_token_program: associated_program_id,
-            offered_amount,
-            wanted_amount,
-            decimals_a: decimals,
+            offered_amount: self.offered_amount,
+            wanted_amount: self.wanted_amount,
+            transfer_fee_a: self.transfer_fee_a,
+            decimals_a: self.decimals_a,
+            decimals_b,
         })
     }
+}
+
+impl SwapFixture {
+    pub fn builder() -> SwapFixtureBuilder {
+        SwapFixtureBuilder::default()
+    }
+
+    pub fn new_default(repo_dir: &Path) -> Result<Self, TestContextError> {
+        Self::builder().build(repo_dir)
+    }
+
+    #[allow(dead_code)]
+    pub fn new_token2022(repo_dir: &Path) -> Result<Self, TestContextError> {
+        Self::builder().token_program(token2022::ID).build(repo_dir)
+    }
+
+    pub fn new_with_amounts(
+        repo_dir: &Path,
+        offered_amount: u64,
+        wanted_amount: u64,
+        maker_balance_a: u64,
+        taker_balance_b: u64,
+        decimals: u8,
+    ) -> Result<Self, TestContextError> {
+        Self::builder()
+            .offered_amount(offered_amount)
+            .wanted_amount(wanted_amount)
+            .maker_balance_a(maker_balance_a)
+            .taker_balance_b(taker_balance_b)
+            .decimals(decimals)
+            .build(repo_dir)
+    }
+
+    /// The discriminator of a swap instruction: the one recorded in the
+    /// program's IDL when available, otherwise Anchor's hashed default.
+    pub fn discriminator(&self, name: &str) -> Vec<u8> {
+        self.discriminators
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| instruction_discriminator(GLOBAL_NAMESPACE, name).to_vec())
+    }
 
     pub fn make_offer_instruction(&self) -> Instruction {
-        let data = build_make_offer_data(self.offer_id, self.offered_amount, self.wanted_amount);
+        self.make_offer_instruction_for(
+            self.offer_id,
+            self.offer,
+            self.vault,
+            self.offered_amount,
+            self.wanted_amount,
+        )
+    }
+
+    fn make_offer_instruction_for(
+        &self,
+        offer_id: u64,
+        offer: Pubkey,
+        vault: Pubkey,
+        offered_amount: u64,
+        wanted_amount: u64,
+    ) -> Instruction {
+        let discriminator = self.discriminator("make_offer");
+        let data = build_make_offer_data(&discriminator, offer_id, offered_amount, wanted_amount);
+        self.make_offer_instruction_with(offer, vault, data)
+    }
+
+    /// Build a `make_offer` for the fixture's offer with caller-supplied
+    /// instruction data, e.g. truncated or otherwise malformed.
+    pub fn make_offer_instruction_with_data(&self, data: Vec<u8>) -> Instruction {
+        self.make_offer_instruction_with(self.offer, self.vault, data)
+    }
+
+    fn make_offer_instruction_with(
+        &self,
+        offer: Pubkey,
+        vault: Pubkey,
+        data: Vec<u8>,
+    ) -> Instruction {
         create_swap_instruction(
             self.program_id,
             data,
@@ -449,8 +878,8 @@ impl SwapFixture {
                 AccountMeta::new_readonly(self.token_mint_a, false),
                 AccountMeta::new_readonly(self.token_mint_b, false),
                 AccountMeta::new(self.maker_token_account_a, false),
-                AccountMeta::new(self.offer, false),
-                AccountMeta::new(self.vault, false),
+                AccountMeta::new(offer, false),
+                AccountMeta::new(vault, false),
                 AccountMeta::new_readonly(solana_system_program::id(), false),
                 AccountMeta::new_readonly(self.token_program, false),
                 AccountMeta::new_readonly(self.associated_token_program, false),
@@ -458,8 +887,53 @@ impl SwapFixture {
         )
     }
 
+    /// Build the fixture's `make_offer` and check its accounts against the
+    /// program's IDL, when one has been generated.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Instruction)` - The instruction, if it matches the IDL or there is none
+    /// * `Err(TestContextError)` - Naming the first account whose position or flags differ
+    #[allow(dead_code)]
+    pub fn make_offer_instruction_checked(
+        &self,
+        repo_dir: &Path,
+    ) -> Result<Instruction, TestContextError> {
+        let instruction = self.make_offer_instruction();
+        check_idl_accounts(repo_dir, "make_offer", &instruction)?;
+        Ok(instruction)
+    }
+
+    /// Build the fixture's `take_offer` and check its accounts against the
+    /// program's IDL, when one has been generated.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Instruction)` - The instruction, if it matches the IDL or there is none
+    /// * `Err(TestContextError)` - Naming the first account whose position or flags differ
+    #[allow(dead_code)]
+    pub fn take_offer_instruction_checked(
+        &self,
+        repo_dir: &Path,
+    ) -> Result<Instruction, TestContextError> {
+        let instruction = self.take_offer_instruction();
+        check_idl_accounts(repo_dir, "take_offer", &instruction)?;
+        Ok(instruction)
+    }
+
     pub fn take_offer_instruction(&self) -> Instruction {
-        let data = build_take_offer_data();
+        let data = build_take_offer_data(&self.discriminator("take_offer"));
+        self.take_offer_instruction_with_data(data)
+    }
+
+    /// Build a `take_offer` that fills only `amount` of token A, for programs
+    /// that support partial fills.
+    pub fn take_offer_partial_instruction(&self, amount: u64) -> Instruction {
+        let data = build_take_offer_partial_data(&self.discriminator("take_offer"), amount);
+        self.take_offer_instruction_with_data(data)
+    }
+
+    fn take_offer_instruction_with_data(&self, data: Vec<u8>) -> Instruction {
         create_swap_instruction(
             self.program_id,
             data,
@@ -480,93 +954,559 @@ impl SwapFixture {
         )
     }
 
+    #[allow(dead_code)]
+    pub fn refund_offer_instruction(&self) -> Instruction {
+        let data = build_refund_offer_data(&self.discriminator("refund_offer"));
+        create_swap_instruction(
+            self.program_id,
+            data,
+            vec![
+                AccountMeta::new(self.maker, true),
+                AccountMeta::new_readonly(self.token_mint_a, false),
+                AccountMeta::new(self.maker_token_account_a, false),
+                AccountMeta::new(self.offer, false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new_readonly(solana_system_program::id(), false),
+                AccountMeta::new_readonly(self.token_program, false),
+                AccountMeta::new_readonly(self.associated_token_program, false),
+            ],
+        )
+    }
+
+    /// Build a `close_offer`, with which the maker reclaims the offer's rent
+    /// after it has been taken, for designs where `take_offer` leaves the
+    /// offer open.
+    #[allow(dead_code)]
+    pub fn close_offer_instruction(&self) -> Instruction {
+        let data = build_close_offer_data(&self.discriminator("close_offer"));
+        create_swap_instruction(
+            self.program_id,
+            data,
+            vec![
+                AccountMeta::new(self.maker, true),
+                AccountMeta::new(self.offer, false),
+                AccountMeta::new_readonly(solana_system_program::id(), false),
+            ],
+        )
+    }
+
     pub fn execute_make_offer(&mut self) -> Result<(), TestContextError> {
         let instruction = self.make_offer_instruction();
         self.context.execute_instruction(&instruction)
     }
 
+    pub fn execute_make_offer_metered(&mut self) -> Result<u64, TestContextError> {
+        let instruction = self.make_offer_instruction();
+        self.context.execute_instruction_metered(&instruction)
+    }
+
     pub fn execute_take_offer(&mut self) -> Result<(), TestContextError> {
         let instruction = self.take_offer_instruction();
         self.context.execute_instruction(&instruction)
     }
 
-    pub fn get_account(&self, pubkey: &Pubkey) -> Result<Account, TestContextError> {
-        self.context
-            .get_account(pubkey)
-            .ok_or_else(|| TestContextError::AccountNotFound(pubkey.to_string()))
+    #[allow(dead_code)]
+    pub fn execute_take_offer_partial(&mut self, amount: u64) -> Result<(), TestContextError> {
+        let instruction = self.take_offer_partial_instruction(amount);
+        self.context.execute_instruction(&instruction)
     }
-}
 
-fn empty_system_account() -> Account {
-    Account {
-        lamports: 0,
-        owner: solana_system_program::id(),
-        data: Vec::new(),
-        ..Default::default()
+    /// Make another offer from the same maker and track it in `offers`.
+    #[allow(dead_code)]
+    pub fn make_additional_offer(
+        &mut self,
+        offer_id: u64,
+        offered_amount: u64,
+        wanted_amount: u64,
+    ) -> Result<Pubkey, TestContextError> {
+        self.make_additional_offer_metered(offer_id, offered_amount, wanted_amount)
+            .map(|(offer, _)| offer)
     }
-}
 
-fn build_make_offer_data(id: u64, offered_amount: u64, wanted_amount: u64) -> Vec<u8> {
-    let mut data = Vec::with_capacity(32);
-    data.extend_from_slice(&anchor_discriminator("global:make_offer"));
-    data.extend_from_slice(&id.to_le_bytes());
-    data.extend_from_slice(&offered_amount.to_le_bytes());
-    data.extend_from_slice(&wanted_amount.to_le_bytes());
-    data
-}
+    /// Like [`SwapFixture::make_additional_offer`], also returning the compute
+    /// units consumed by `make_offer`.
+    pub fn make_additional_offer_metered(
+        &mut self,
+        offer_id: u64,
+        offered_amount: u64,
+        wanted_amount: u64,
+    ) -> Result<(Pubkey, u64), TestContextError> {
+        let (offer, _bump) =
+            find_offer_address(self.offer_seeds, &self.maker, offer_id, &self.program_id);
+        let vault = get_associated_token_address_with_program_id(
+            &offer,
+            &self.token_mint_a,
+            &self.token_program,
+        );
 
-fn build_take_offer_data() -> Vec<u8> {
-    anchor_discriminator("global:take_offer").to_vec()
-}
+        self.context.add_account(offer, empty_system_account());
+        self.context.add_account(vault, empty_system_account());
 
-fn anchor_discriminator(name: &str) -> [u8; 8] {
-    let mut hasher = Sha256::new();
-    hasher.update(name.as_bytes());
-    let hash = hasher.finalize();
-    let mut out = [0u8; 8];
-    out.copy_from_slice(&hash[..8]);
-    out
-}
+        let instruction =
+            self.make_offer_instruction_for(offer_id, offer, vault, offered_amount, wanted_amount);
+        let consumed = self.context.execute_instruction_metered(&instruction)?;
 
-fn read_pubkey(data: &[u8]) -> Result<Pubkey, TestContextError> {
-    let bytes: [u8; 32] = data
-        .try_into()
-        .map_err(|_| TestContextError::ValidationError("Invalid pubkey bytes".to_string()))?;
-    Ok(Pubkey::new_from_array(bytes))
-}
+        self.offers.insert(offer_id, (offer, vault));
+        Ok((offer, consumed))
+    }
 
-fn read_u64(data: &[u8]) -> Result<u64, TestContextError> {
-    let bytes: [u8; 8] = data
-        .try_into()
-        .map_err(|_| TestContextError::ValidationError("Invalid u64 bytes".to_string()))?;
-    Ok(u64::from_le_bytes(bytes))
-}
+    /// Move `amount` of mint A from the maker to the taker with
+    /// `transfer_checked`, passing `decimals` rather than the mint's.
+    pub fn execute_maker_transfer_checked(
+        &mut self,
+        amount: u64,
+        decimals: u8,
+    ) -> Result<(), TestContextError> {
+        let instruction = spl_token_interface::instruction::transfer_checked(
+            &self.token_program,
+            &self.maker_token_account_a,
+            &self.token_mint_a,
+            &self.taker_token_account_a,
+            &self.maker,
+            &[],
+            amount,
+            decimals,
+        )
+        .map_err(|err| TestContextError::ValidationError(err.to_string()))?;
+        self.context.execute_instruction(&instruction)
+    }
 
-fn token_account_amount(account: &Account) -> Result<u64, TestContextError> {
-    if account.data.len() < 72 {
-        return Err(TestContextError::ValidationError("Token account data too short".to_string()));
+    #[allow(dead_code)]
+    pub fn execute_refund_offer(&mut self) -> Result<(), TestContextError> {
+        let instruction = self.refund_offer_instruction();
+        self.context.execute_instruction(&instruction)
     }
-    read_u64(&account.data[64..72])
-}
 
-fn token_account_owner(account: &Account) -> Result<Pubkey, TestContextError> {
-    if account.data.len() < 64 {
-        return Err(TestContextError::ValidationError("Token account data too short".to_string()));
+    #[allow(dead_code)]
+    pub fn execute_close_offer(&mut self) -> Result<(), TestContextError> {
+        let instruction = self.close_offer_instruction();
+        self.context.execute_instruction(&instruction)
     }
-    read_pubkey(&account.data[32..64])
-}
+
+    pub fn get_account(&self, pubkey: &Pubkey) -> Result<Account, TestContextError> {
+        self.context
+            .get_account(pubkey)
+            .ok_or_else(|| TestContextError::AccountNotFound(pubkey.to_string()))
+    }
+
+    /// Decode the current token balances of the user accounts and the vault.
+    pub fn balances(&self) -> Result<BalanceSnapshot, TestContextError> {
+        Ok(BalanceSnapshot {
+            maker_a: self.balance_of(&self.maker_token_account_a)?,
+            maker_b: self.balance_of(&self.maker_token_account_b)?,
+            taker_a: self.balance_of(&self.taker_token_account_a)?,
+            taker_b: self.balance_of(&self.taker_token_account_b)?,
+            vault_a: self.balance_of(&self.vault)?,
+        })
+    }
+
+    /// Make and take the fixture's offer, recording balances on both sides of
+    /// the swap and whether the offer and vault were closed.
+    pub fn run_full_swap(&mut self) -> Result<SwapOutcome, TestContextError> {
+        let before = self.balances()?;
+        make_offer_success(self)?;
+        take_offer_success(self)?;
+
+        Ok(SwapOutcome {
+            before,
+            after: self.balances()?,
+            offer_closed: self.context.is_closed(&self.offer),
+            vault_closed: self.context.is_closed(&self.vault),
+        })
+    }
+
+    /// Read a token account's balance, treating a missing or closed account
+    /// as empty.
+    fn balance_of(&self, pubkey: &Pubkey) -> Result<u64, TestContextError> {
+        match self.context.get_account(pubkey) {
+            Some(account) if !account.data.is_empty() => token_account_amount(&account),
+            _ => Ok(0),
+        }
+    }
+
+    /// Check that the fixture's derived addresses are internally consistent.
+    ///
+    /// Re-derives the four user ATAs, and the offer PDA and vault ATA of every
+    /// tracked offer, from the stored fields. No instruction is run, so this
+    /// catches fixture construction mistakes before the program is involved.
+    #[allow(dead_code)]
+    pub fn validate_wiring(&self) -> Result<(), TestContextError> {
+        let ata = |owner: &Pubkey, mint: &Pubkey| {
+            get_associated_token_address_with_program_id(owner, mint, &self.token_program)
+        };
+        let atas = [
+            ("maker token A ATA", ata(&self.maker, &self.token_mint_a), self.maker_token_account_a),
+            ("maker token B ATA", ata(&self.maker, &self.token_mint_b), self.maker_token_account_b),
+            ("taker token A ATA", ata(&self.taker, &self.token_mint_a), self.taker_token_account_a),
+            ("taker token B ATA", ata(&self.taker, &self.token_mint_b), self.taker_token_account_b),
+        ];
+        for (field, expected, actual) in atas {
+            ensure_eq(field, expected, actual)?;
+        }
+
+        if self.offers.get(&self.offer_id) != Some(&(self.offer, self.vault)) {
+            return Err(TestContextError::ValidationError(format!(
+                "offer id {} is not tracked with the current offer and vault",
+                self.offer_id
+            )));
+        }
+        for (offer_id, (offer, vault)) in &self.offers {
+            let (expected_offer, _bump) =
+                find_offer_address(self.offer_seeds, &self.maker, *offer_id, &self.program_id);
+            ensure_eq("offer PDA", expected_offer, *offer)?;
+            ensure_eq("vault ATA", ata(offer, &self.token_mint_a), *vault)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that the fixture funds the swap it describes.
+    ///
+    /// The maker's token A account must hold at least `offered_amount` and the
+    /// taker's token B account at least `wanted_amount`. A failure here points
+    /// at the fixture setup rather than at the program under test.
+    pub fn validate_preconditions(&self) -> Result<(), TestContextError> {
+        let requirements = [
+            ("maker token A account", self.maker_token_account_a, self.offered_amount),
+            ("taker token B account", self.taker_token_account_b, self.wanted_amount),
+        ];
+        for (name, pubkey, required) in requirements {
+            let account = self.get_account(&pubkey)?;
+            let amount = token_account_amount(&account)?;
+            if amount < required {
+                return Err(TestContextError::ValidationError(format!(
+                    "{} {} holds {} tokens but the swap needs {}",
+                    name, pubkey, amount, required
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Amount of token A that arrives when `amount` is transferred, after any
+    /// transfer fee on mint A.
+    pub fn amount_after_fee_a(&self, amount: u64) -> u64 {
+        amount.saturating_sub(self.transfer_fee_a.map_or(0, |fee| fee.fee_for(amount)))
+    }
+
+    /// Vault balance expected after `make_offer`.
+    pub fn expected_vault_amount(&self) -> u64 {
+        self.amount_after_fee_a(self.offered_amount)
+    }
+
+    /// Taker token A balance expected after `take_offer` empties the vault.
+    pub fn expected_taker_amount_a(&self) -> u64 {
+        self.amount_after_fee_a(self.expected_vault_amount())
+    }
+
+    /// Install a rent-exempt account with `data` at the offer PDA, replacing
+    /// the empty placeholder, so `make_offer` finds the account already in
+    /// use.
+    #[allow(dead_code)]
+    pub fn seed_offer_data(&mut self, data: Vec<u8>, owner: Pubkey) {
+        let account = Account { data, owner, ..Default::default() };
+        self.context.add_rent_exempt_account(self.offer, account);
+    }
+
+    /// Add a token account at a random address instead of the derived ATA.
+    pub fn add_raw_token_account(&mut self, mint: Pubkey, owner: Pubkey, amount: u64) -> Pubkey {
+        let pubkey = Pubkey::new_unique();
+        let account =
+            token_account(mint, owner, amount, AccountState::Initialized, &self.token_program);
+        self.context.add_rent_exempt_account(pubkey, account);
+        pubkey
+    }
+
+    #[allow(dead_code)]
+    pub fn snapshot(&self) -> AccountSnapshot {
+        self.context.snapshot()
+    }
+
+    #[allow(dead_code)]
+    pub fn restore(&mut self, snapshot: AccountSnapshot) {
+        self.context.restore(snapshot);
+    }
+
+    #[allow(dead_code)]
+    pub fn set_rent(&mut self, rent: Rent) {
+        self.context.set_rent(rent);
+    }
+
+    #[allow(dead_code)]
+    pub fn set_clock(&mut self, unix_timestamp: i64, slot: u64) {
+        self.context.set_clock(unix_timestamp, slot);
+    }
+
+    #[allow(dead_code)]
+    pub fn warp_forward(&mut self, seconds: u64) {
+        self.context.warp_forward(seconds);
+    }
+
+    #[allow(dead_code)]
+    pub fn reset_clock(&mut self) {
+        self.context.reset_clock();
+    }
+}
+
+/// Create a mint account owned by the given token program.
+///
+/// The base mint layout is shared by SPL Token and Token-2022, so only the
+/// owner differs unless a transfer fee extension is requested.
+fn mint_account(
+    mint: Mint,
+    token_program: &Pubkey,
+    transfer_fee: Option<TransferFee>,
+) -> Result<Account, TestContextError> {
+    let account = Account { owner: *token_program, ..token::create_account_for_mint(mint) };
+    match transfer_fee {
+        Some(fee) => with_extensions(
+            account,
+            ACCOUNT_TYPE_MINT,
+            &[(EXTENSION_TRANSFER_FEE_CONFIG, fee.config_bytes())],
+        ),
+        None => Ok(account),
+    }
+}
+
+/// Add the `TransferFeeAmount` extension that Token-2022 requires on token
+/// accounts of a transfer-fee mint.
+fn with_transfer_fee_amount(account: Account) -> Result<Account, TestContextError> {
+    with_extensions(
+        account,
+        ACCOUNT_TYPE_ACCOUNT,
+        &[(EXTENSION_TRANSFER_FEE_AMOUNT, 0u64.to_le_bytes())],
+    )
+}
+
+/// Create a token account owned by the given token program.
+fn token_account(
+    mint: Pubkey,
+    owner: Pubkey,
+    amount: u64,
+    state: AccountState,
+    token_program: &Pubkey,
+) -> Account {
+    let account = token::create_account_for_token_account(TokenAccount {
+        mint,
+        owner,
+        amount,
+        delegate: COption::None,
+        state,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    });
+    Account { owner: *token_program, ..account }
+}
+
+/// Builds the offer PDA seeds, without the bump, for a maker and offer id.
+pub type OfferSeeds = fn(&Pubkey, u64) -> Vec<Vec<u8>>;
+
+/// Default offer PDA seeds: `[b"offer", maker, id]`.
+///
+/// The program declares the offer id as a `u64` and seeds the PDA with its
+/// 8-byte little-endian encoding, matching the id serialized in the
+/// `make_offer` instruction data.
+pub fn offer_seeds(maker: &Pubkey, offer_id: u64) -> Vec<Vec<u8>> {
+    vec![OFFER_SEED_PREFIX.to_vec(), maker.to_bytes().to_vec(), offer_id.to_le_bytes().to_vec()]
+}
+
+/// Derive the offer PDA for a maker and offer id with the given seed scheme.
+fn find_offer_address(
+    seeds: OfferSeeds,
+    maker: &Pubkey,
+    offer_id: u64,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    let seeds = seeds(maker, offer_id);
+    let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+    Pubkey::find_program_address(&seeds, program_id)
+}
+
+fn empty_system_account() -> Account {
+    Account {
+        lamports: 0,
+        owner: solana_system_program::id(),
+        data: Vec::new(),
+        ..Default::default()
+    }
+}
+
+fn build_make_offer_data(
+    discriminator: &[u8],
+    id: u64,
+    offered_amount: u64,
+    wanted_amount: u64,
+) -> Vec<u8> {
+    let mut data = Vec::with_capacity(32);
+    data.extend_from_slice(discriminator);
+    data.extend_from_slice(&id.to_le_bytes());
+    data.extend_from_slice(&offered_amount.to_le_bytes());
+    data.extend_from_slice(&wanted_amount.to_le_bytes());
+    data
+}
+
+fn build_take_offer_data(discriminator: &[u8]) -> Vec<u8> {
+    discriminator.to_vec()
+}
+
+fn build_take_offer_partial_data(discriminator: &[u8], amount: u64) -> Vec<u8> {
+    let mut data = discriminator.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    data
+}
+
+fn build_refund_offer_data(discriminator: &[u8]) -> Vec<u8> {
+    discriminator.to_vec()
+}
+
+fn build_close_offer_data(discriminator: &[u8]) -> Vec<u8> {
+    discriminator.to_vec()
+}
+
+/// Compute the Anchor discriminator of an instruction in a namespace.
+///
+/// Anchor places program instructions in the `global` namespace; see
+/// [`GLOBAL_NAMESPACE`].
+///
+/// # Arguments
+///
+/// * `namespace` - The discriminator namespace, e.g. `global`
+/// * `name` - The instruction name in snake case, e.g. `make_offer`
+///
+/// # Returns
+///
+/// * `[u8; 8]` - The first 8 bytes of `sha256("<namespace>:<name>")`
+pub fn instruction_discriminator(namespace: &str, name: &str) -> [u8; 8] {
+    anchor_discriminator(&format!("{}:{}", namespace, name))
+}
+
+fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let mut hasher = Sha256::new();
+    hasher.update(name.as_bytes());
+    let hash = hasher.finalize();
+    let mut out = [0u8; 8];
+    out.copy_from_slice(&hash[..8]);
+    out
+}
+
+fn read_pubkey(data: &[u8]) -> Result<Pubkey, TestContextError> {
+    let bytes: [u8; 32] = data
+        .try_into()
+        .map_err(|_| TestContextError::ValidationError("Invalid pubkey bytes".to_string()))?;
+    Ok(Pubkey::new_from_array(bytes))
+}
+
+fn read_u64(data: &[u8]) -> Result<u64, TestContextError> {
+    let bytes: [u8; 8] = data
+        .try_into()
+        .map_err(|_| TestContextError::ValidationError("Invalid u64 bytes".to_string()))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn token_account_amount(account: &Account) -> Result<u64, TestContextError> {
+    if account.data.len() < 72 {
+        return Err(TestContextError::data_too_short("Token account", &account.data, 72));
+    }
+    read_u64(&account.data[64..72])
+}
+
+fn token_account_owner(account: &Account) -> Result<Pubkey, TestContextError> {
+    if account.data.len() < 64 {
+        return Err(TestContextError::data_too_short("Token account", &account.data, 64));
+    }
+    read_pubkey(&account.data[32..64])
+}
 
 fn token_account_mint(account: &Account) -> Result<Pubkey, TestContextError> {
     if account.data.len() < 32 {
-        return Err(TestContextError::ValidationError("Token account data too short".to_string()));
+        return Err(TestContextError::data_too_short("Token account", &account.data, 32));
     }
     read_pubkey(&account.data[0..32])
 }
 
+/// Read the delegate of a token account.
+///
+/// The delegate is a `COption<Pubkey>` at bytes 72..108: a little-endian
+/// `u32` tag (0 for `None`, 1 for `Some`) followed by the 32-byte key.
+#[allow(dead_code)]
+pub fn token_account_delegate(account: &Account) -> Result<Option<Pubkey>, TestContextError> {
+    if account.data.len() < 108 {
+        return Err(TestContextError::data_too_short("Token account", &account.data, 108));
+    }
+    read_optional_pubkey(&account.data[72..108], "token account delegate")
+}
+
+/// Read the amount a token account's delegate is approved to transfer.
+#[allow(dead_code)]
+pub fn token_account_delegated_amount(account: &Account) -> Result<u64, TestContextError> {
+    if account.data.len() < 129 {
+        return Err(TestContextError::data_too_short("Token account", &account.data, 129));
+    }
+    read_u64(&account.data[121..129])
+}
+
+/// Read the mint authority of a mint account.
+///
+/// The authority is a `COption<Pubkey>` at bytes 0..36, laid out like a token
+/// account's delegate.
+#[allow(dead_code)]
+pub fn mint_authority(account: &Account) -> Result<Option<Pubkey>, TestContextError> {
+    if account.data.len() < 36 {
+        return Err(TestContextError::data_too_short("Mint", &account.data, 36));
+    }
+    read_optional_pubkey(&account.data[0..36], "mint authority")
+}
+
+/// Read the total supply of a mint account.
+#[allow(dead_code)]
+pub fn mint_supply(account: &Account) -> Result<u64, TestContextError> {
+    if account.data.len() < 44 {
+        return Err(TestContextError::data_too_short("Mint", &account.data, 44));
+    }
+    read_u64(&account.data[36..44])
+}
+
+/// Read the decimals of a mint account.
+#[allow(dead_code)]
+pub fn mint_decimals(account: &Account) -> Result<u8, TestContextError> {
+    if account.data.len() < 45 {
+        return Err(TestContextError::data_too_short("Mint", &account.data, 45));
+    }
+    Ok(account.data[44])
+}
+
+/// Read a `COption<Pubkey>`: a little-endian `u32` tag (0 for `None`, 1 for
+/// `Some`) followed by the 32-byte key.
+fn read_optional_pubkey(data: &[u8], field: &str) -> Result<Option<Pubkey>, TestContextError> {
+    match data[0..4] {
+        [0, 0, 0, 0] => Ok(None),
+        [1, 0, 0, 0] => read_pubkey(&data[4..36]).map(Some),
+        _ => Err(TestContextError::ValidationError(format!("Invalid {} tag", field))),
+    }
+}
+
 fn offer_data_from_account(account: &Account) -> Result<OfferData, TestContextError> {
     if account.data.len() < 8 + 8 + 32 + 32 + 32 + 8 + 1 {
-        return Err(TestContextError::ValidationError("Offer account data too short".to_string()));
+        return Err(TestContextError::data_too_short(
+            "Offer account",
+            &account.data,
+            8 + 8 + 32 + 32 + 32 + 8 + 1,
+        ));
     }
+
+    // Reject other account types before the fixed offsets decode garbage
+    let expected = anchor_discriminator(OFFER_ACCOUNT_DISCRIMINATOR_NAME);
+    if account.data[..8] != expected {
+        return Err(TestContextError::ValidationError(format!(
+            "Offer account discriminator mismatch: expected {} (sha256(\"{}\")), got {}; the \
+             account is not an `Offer` or its struct is named differently",
+            hex(&expected),
+            OFFER_ACCOUNT_DISCRIMINATOR_NAME,
+            hex(&account.data[..8])
+        )));
+    }
+
     let mut offset = 8;
     let id = read_u64(&account.data[offset..offset + 8])?;
     offset += 8;
@@ -583,7 +1523,53 @@ fn offer_data_from_account(account: &Account) -> Result<OfferData, TestContextEr
     Ok(OfferData { id, maker, token_mint_a, token_mint_b, token_b_wanted_amount, bump })
 }
 
+/// Compare an instruction's account metas with the accounts the IDL declares
+/// for it, position by position. Passes when no IDL is available.
+fn check_idl_accounts(
+    repo_dir: &Path,
+    name: &str,
+    instruction: &Instruction,
+) -> Result<(), TestContextError> {
+    let Some(expected) = load_idl_instruction_accounts(repo_dir, name) else {
+        return Ok(());
+    };
+    ensure_eq(&format!("{} account count", name), expected.len(), instruction.accounts.len())?;
+
+    for (index, (account, meta)) in expected.iter().zip(&instruction.accounts).enumerate() {
+        if account.signer != meta.is_signer || account.writable != meta.is_writable {
+            return Err(TestContextError::ValidationError(format!(
+                "{} account {} ({}) is signer={} writable={} in the IDL, but the fixture passes \
+                 signer={} writable={}",
+                name,
+                index,
+                account.name,
+                account.signer,
+                account.writable,
+                meta.is_signer,
+                meta.is_writable
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Fail with a [`TestContextError::mismatch`] unless `actual` equals
+/// `expected`.
+fn ensure_eq<T: PartialEq + std::fmt::Display>(
+    field: &str,
+    expected: T,
+    actual: T,
+) -> Result<(), TestContextError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(TestContextError::mismatch(field, expected, actual))
+    }
+}
+
 fn make_offer_success(fixture: &mut SwapFixture) -> Result<(), TestContextError> {
+    fixture.validate_preconditions()?;
     fixture.execute_make_offer()
 }
 
@@ -593,12 +1579,6 @@ fn take_offer_success(fixture: &mut SwapFixture) -> Result<(), TestContextError>
 
 pub fn run_env_setup_check() -> Result<(), tester::CaseError> {
     let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
-    if !repo_path.exists() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Repository directory not found: {}", repo_path.display()),
-        )) as Box<dyn std::error::Error + Send + Sync>);
-    }
     check_program_available(&repo_path)?;
     run_make_offer_smoke(&repo_path)
 }
@@ -614,15 +1594,10 @@ pub fn run_solana_model_check() -> Result<(), tester::CaseError> {
     match fixture.execute_make_offer() {
         Ok(()) => {
             let offer_account = fixture.get_account(&fixture.offer)?;
-            if offer_account.owner != fixture.program_id {
-                return Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "Offer account owner does not match program id",
-                )) as Box<dyn std::error::Error + Send + Sync>);
-            }
+            ensure_eq("offer account owner", fixture.program_id, offer_account.owner)?;
             Ok(())
         }
-        Err(TestContextError::ExecutionError(_)) => Ok(()),
+        Err(TestContextError::ExecutionError(_) | TestContextError::ProgramAborted(_)) => Ok(()),
         Err(err) => Err(to_case_error(err)),
     }
 }
@@ -631,10 +1606,9 @@ pub fn run_anchor_try_check() -> Result<(), tester::CaseError> {
     let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
     let program_id = load_swap_program_id(&repo_path).map_err(to_case_error_from_load)?;
     if program_id == Pubkey::default() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Program ID is still default",
-        )) as Box<dyn std::error::Error + Send + Sync>);
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Program ID is still default".to_string(),
+        )));
     }
     run_make_offer_smoke(&repo_path)
 }
@@ -645,98 +1619,460 @@ pub fn run_spl_token_basics_check() -> Result<(), tester::CaseError> {
     make_offer_success(&mut fixture).map_err(to_case_error)?;
     let vault_account = fixture.get_account(&fixture.vault)?;
     let vault_mint = token_account_mint(&vault_account).map_err(to_case_error_from_context)?;
-    if vault_mint != fixture.token_mint_a {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Vault mint mismatch",
-        )) as Box<dyn std::error::Error + Send + Sync>);
-    }
+    ensure_eq("vault mint", fixture.token_mint_a, vault_mint)?;
     Ok(())
 }
 
+/// Require `make_offer` to move the offered tokens into the vault through
+/// the token program.
+///
+/// Only an account's owner may change its data, so a debit from the maker's
+/// token account and a credit to a vault owned by the token program can only
+/// come from a CPI into that program; a program that writes the vault's
+/// balance bytes itself fails here.
 pub fn run_cpi_transfer_check() -> Result<(), tester::CaseError> {
     let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
     let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    let initial_maker_amount = fixture.balances()?.maker_a;
+
     make_offer_success(&mut fixture).map_err(to_case_error)?;
 
     let vault_account = fixture.get_account(&fixture.vault)?;
-    let vault_amount = token_account_amount(&vault_account).map_err(to_case_error_from_context)?;
-    if vault_amount != fixture.offered_amount {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Vault balance does not match offered amount",
-        )) as Box<dyn std::error::Error + Send + Sync>);
+    ensure_eq("vault owner", fixture.token_program, vault_account.owner)?;
+
+    let maker_debit = initial_maker_amount.checked_sub(fixture.balances()?.maker_a);
+    if maker_debit != Some(fixture.offered_amount) {
+        return Err(to_case_error(TestContextError::ValidationError(format!(
+            "Maker token A debit mismatch: expected {}, got {}; make_offer must transfer the \
+             offered tokens through the token program",
+            fixture.offered_amount,
+            maker_debit.map_or_else(|| "a credit".to_string(), |debit| debit.to_string())
+        ))));
     }
+
+    let vault_amount = token_account_amount(&vault_account).map_err(to_case_error_from_context)?;
+    ensure_eq("vault balance", fixture.expected_vault_amount(), vault_amount)?;
     Ok(())
 }
 
-pub fn run_token_transfer_check() -> Result<(), tester::CaseError> {
+/// Run make and take offer against a Token-2022 mint A that charges a
+/// transfer fee, expecting each transfer to deliver the post-fee amount.
+#[allow(dead_code)]
+pub fn run_transfer_fee_check() -> Result<(), tester::CaseError> {
     let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
-    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    let mut fixture = SwapFixture::builder()
+        .transfer_fee_a(TRANSFER_FEE_BASIS_POINTS, TRANSFER_FEE_MAXIMUM)
+        .build(&repo_path)
+        .map_err(to_case_error)?;
     make_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    let vault_account = fixture.get_account(&fixture.vault)?;
+    let vault_amount = token_account_amount(&vault_account).map_err(to_case_error_from_context)?;
+    ensure_eq(
+        "vault balance after the transfer fee",
+        fixture.expected_vault_amount(),
+        vault_amount,
+    )?;
+
     take_offer_success(&mut fixture).map_err(to_case_error)?;
 
     let taker_token_a = fixture.get_account(&fixture.taker_token_account_a)?;
-    let maker_token_b = fixture.get_account(&fixture.maker_token_account_b)?;
     let taker_amount = token_account_amount(&taker_token_a).map_err(to_case_error_from_context)?;
-    let maker_amount = token_account_amount(&maker_token_b).map_err(to_case_error_from_context)?;
+    ensure_eq(
+        "taker token A balance after the transfer fee",
+        fixture.expected_taker_amount_a(),
+        taker_amount,
+    )?;
+
+    Ok(())
+}
+
+/// Require `make_offer` to move tokens through the token program; see
+/// [`run_cpi_transfer_check`].
+pub fn run_cpi_concept_check() -> Result<(), tester::CaseError> {
+    run_cpi_transfer_check()
+}
 
-    if taker_amount != fixture.offered_amount || maker_amount != fixture.wanted_amount {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Token balances did not transfer as expected",
-        )) as Box<dyn std::error::Error + Send + Sync>);
+pub fn run_token_transfer_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    let outcome = fixture.run_full_swap()?;
+
+    ensure_eq("taker token A balance", fixture.expected_taker_amount_a(), outcome.after.taker_a)?;
+    ensure_eq("maker token B balance", fixture.wanted_amount, outcome.after.maker_b)?;
+
+    Ok(())
+}
+
+/// Make an offer whose mint A has 9 decimals while mint B keeps the default
+/// 6.
+///
+/// The check first confirms that a `transfer_checked` passing the default 6
+/// against mint A is rejected by the token program, which is how a program
+/// hard-coding the decimals fails. `make_offer` must then succeed, and a
+/// failure is reported with that hint.
+pub fn run_decimals_mismatch_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::builder()
+        .decimals_a(MISMATCHED_MINT_DECIMALS_A)
+        .build(&repo_path)
+        .map_err(to_case_error)?;
+
+    match fixture.execute_maker_transfer_checked(fixture.offered_amount, DEFAULT_MINT_DECIMALS) {
+        Ok(()) => {
+            return Err(to_case_error(TestContextError::expected_failure(format!(
+                "transfer_checked with {} decimals accepted by a mint with {}",
+                DEFAULT_MINT_DECIMALS, MISMATCHED_MINT_DECIMALS_A
+            ))));
+        }
+        Err(TestContextError::ExecutionError(_)) => {}
+        Err(err) => return Err(to_case_error(err)),
+    }
+
+    match make_offer_success(&mut fixture) {
+        Err(err @ (TestContextError::ExecutionError(_) | TestContextError::ProgramAborted(_))) => {
+            Err(to_case_error(TestContextError::ValidationError(format!(
+                "make_offer failed with mint A at {} decimals and mint B at {}; the program \
+                 likely passes hard-coded decimals to transfer_checked instead of the mint's: {}",
+                MISMATCHED_MINT_DECIMALS_A, DEFAULT_MINT_DECIMALS, err
+            ))))
+        }
+        result => result.map_err(to_case_error),
+    }
+}
+
+/// Run the full swap with mints whose decimals differ from the common `6`.
+///
+/// `transfer_checked` asserts the decimals argument against the mint, so a
+/// program that hard-codes the decimals instead of reading them from the mint
+/// fails here, and a failed make or take is reported with that hint.
+pub fn run_transfer_checked_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    for (decimals_a, decimals_b) in UNUSUAL_MINT_DECIMALS {
+        let mut fixture = SwapFixture::builder()
+            .decimals_a(decimals_a)
+            .decimals_b(decimals_b)
+            .build(&repo_path)
+            .map_err(to_case_error)?;
+        let with_hint = |err: TestContextError| match err {
+            TestContextError::ExecutionError(_) | TestContextError::ProgramAborted(_) => {
+                TestContextError::ValidationError(format!(
+                    "swap failed with mint A at {} decimals and mint B at {}; the program likely \
+                     passes hard-coded decimals to transfer_checked instead of the mint's: {}",
+                    decimals_a, decimals_b, err
+                ))
+            }
+            err => err,
+        };
+        make_offer_success(&mut fixture).map_err(with_hint).map_err(to_case_error)?;
+        take_offer_success(&mut fixture).map_err(with_hint).map_err(to_case_error)?;
+
+        let taker_token_a = fixture.get_account(&fixture.taker_token_account_a)?;
+        let maker_token_b = fixture.get_account(&fixture.maker_token_account_b)?;
+        let taker_amount =
+            token_account_amount(&taker_token_a).map_err(to_case_error_from_context)?;
+        let maker_amount =
+            token_account_amount(&maker_token_b).map_err(to_case_error_from_context)?;
+
+        ensure_eq(
+            &format!("taker token A balance with mint decimals {}/{}", decimals_a, decimals_b),
+            fixture.expected_taker_amount_a(),
+            taker_amount,
+        )?;
+        ensure_eq(
+            &format!("maker token B balance with mint decimals {}/{}", decimals_a, decimals_b),
+            fixture.wanted_amount,
+            maker_amount,
+        )?;
     }
 
     Ok(())
 }
 
-pub fn run_offer_checks() -> Result<(), tester::CaseError> {
+/// Run the full swap and require the offer PDA and vault to be closed.
+pub fn run_account_closure_check() -> Result<(), tester::CaseError> {
     let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
     let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
     make_offer_success(&mut fixture).map_err(to_case_error)?;
-    let offer_account = fixture.get_account(&fixture.offer)?;
-    let offer = offer_data_from_account(&offer_account).map_err(to_case_error_from_context)?;
+    take_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    if !fixture.context.is_closed(&fixture.offer) {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Offer account was not closed after take_offer".to_string(),
+        )));
+    }
 
-    if offer.id != fixture.offer_id ||
-        offer.maker != fixture.maker ||
-        offer.token_mint_a != fixture.token_mint_a ||
-        offer.token_mint_b != fixture.token_mint_b ||
-        offer.token_b_wanted_amount != fixture.wanted_amount
-    {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Offer account data mismatch",
-        )) as Box<dyn std::error::Error + Send + Sync>);
+    if !fixture.context.is_closed(&fixture.vault) {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Vault account was not closed after take_offer".to_string(),
+        )));
     }
 
     Ok(())
 }
 
-pub fn run_make_offer_checks() -> Result<(), tester::CaseError> {
+/// Run the full swap and require the total of each mint across the user
+/// token accounts and the vault to be unchanged, so no tokens were minted or
+/// burned along the way.
+#[allow(dead_code)]
+pub fn run_conservation_check() -> Result<(), tester::CaseError> {
     let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
     let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    let before = fixture.balances().map_err(to_case_error)?;
+
     make_offer_success(&mut fixture).map_err(to_case_error)?;
+    take_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    let after = fixture.balances().map_err(to_case_error)?;
+    ensure_eq("total token A supply", before.total_a(), after.total_a())?;
+    ensure_eq("total token B supply", before.total_b(), after.total_b())?;
+
+    Ok(())
+}
+
+/// Take part of an offer and require the remainder to stay in the vault with
+/// the offer still open.
+#[allow(dead_code)]
+pub fn run_partial_fill_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+    fixture.execute_take_offer_partial(PARTIAL_FILL_AMOUNT).map_err(to_case_error)?;
 
-    let maker_token_account = fixture.get_account(&fixture.maker_token_account_a)?;
     let vault_account = fixture.get_account(&fixture.vault)?;
+    let vault_amount = token_account_amount(&vault_account).map_err(to_case_error_from_context)?;
+    ensure_eq("vault balance", fixture.offered_amount - PARTIAL_FILL_AMOUNT, vault_amount)?;
+
+    let taker_token_a = fixture.get_account(&fixture.taker_token_account_a)?;
+    let taker_amount = token_account_amount(&taker_token_a).map_err(to_case_error_from_context)?;
+    ensure_eq("taker token A balance", PARTIAL_FILL_AMOUNT, taker_amount)?;
+
+    if fixture.context.is_closed(&fixture.offer) {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Offer account was closed after a partial fill".to_string(),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Make an offer, refund it, and require the maker's token A balance to be
+/// fully restored and the vault to be closed.
+#[allow(dead_code)]
+pub fn run_refund_checks() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+
+    let maker_token_account = fixture.get_account(&fixture.maker_token_account_a)?;
+    let initial_amount =
+        token_account_amount(&maker_token_account).map_err(to_case_error_from_context)?;
+
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+    fixture.execute_refund_offer().map_err(to_case_error)?;
 
+    let maker_token_account = fixture.get_account(&fixture.maker_token_account_a)?;
     let maker_amount =
         token_account_amount(&maker_token_account).map_err(to_case_error_from_context)?;
-    let vault_amount = token_account_amount(&vault_account).map_err(to_case_error_from_context)?;
 
-    if maker_amount != 0 || vault_amount != fixture.offered_amount {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Make offer transfer did not move tokens to vault",
-        )) as Box<dyn std::error::Error + Send + Sync>);
+    ensure_eq("maker token A balance after refund_offer", initial_amount, maker_amount)?;
+
+    if !fixture.context.is_closed(&fixture.vault) {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Vault account was not closed after refund_offer".to_string(),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Take an offer and require the rent held by the offer account to be
+/// refunded to the maker when `take_offer` closes it.
+#[allow(dead_code)]
+pub fn run_rent_refund_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    let offer_rent = fixture.get_account(&fixture.offer)?.lamports;
+    let snapshot = fixture.snapshot();
+    take_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    fixture.context.assert_lamport_delta(&snapshot, &fixture.maker, i128::from(offer_rent))?;
+    Ok(())
+}
+
+/// Make and take an offer, then close it, and require the offer's rent to
+/// return to the maker with the offer closed.
+///
+/// Only applies to designs where `take_offer` leaves the offer open for a
+/// separate `close_offer`; it is kept apart from [`run_refund_checks`], which
+/// cancels an offer that was never taken.
+#[allow(dead_code)]
+pub fn run_close_offer_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+    take_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    if fixture.context.is_closed(&fixture.offer) {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Offer account was already closed by take_offer, so there is nothing to close"
+                .to_string(),
+        )));
+    }
+
+    let offer_lamports = fixture.get_account(&fixture.offer)?.lamports;
+    let maker_lamports = fixture.get_account(&fixture.maker)?.lamports;
+    fixture.execute_close_offer().map_err(to_case_error)?;
+
+    ensure_eq(
+        "maker lamports after close_offer",
+        maker_lamports + offer_lamports,
+        fixture.get_account(&fixture.maker)?.lamports,
+    )?;
+    if !fixture.context.is_closed(&fixture.offer) {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Offer account was not closed after close_offer".to_string(),
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn run_offer_checks() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    let program_accounts = fixture.context.accounts_owned_by(&fixture.program_id);
+    if program_accounts.len() != 1 || program_accounts[0].0 != fixture.offer {
+        return Err(to_case_error(TestContextError::ValidationError(format!(
+            "Expected the offer to be the only account owned by the program, found {}",
+            program_accounts.len()
+        ))));
+    }
+
+    let offer_account = fixture.get_account(&fixture.offer)?;
+    let offer = offer_data_from_account(&offer_account).map_err(to_case_error_from_context)?;
+
+    ensure_eq("offer id", fixture.offer_id, offer.id)?;
+    ensure_eq("offer maker", fixture.maker, offer.maker)?;
+    ensure_eq("offer token_mint_a", fixture.token_mint_a, offer.token_mint_a)?;
+    ensure_eq("offer token_mint_b", fixture.token_mint_b, offer.token_mint_b)?;
+    ensure_eq("offer token_b_wanted_amount", fixture.wanted_amount, offer.token_b_wanted_amount)?;
+
+    Ok(())
+}
+
+/// Make `count` offers with distinct ids from the same maker.
+///
+/// Every offer account must decode to its own id and terms, exactly `count`
+/// accounts must end up owned by the program, and the offers together must
+/// stay within [`MAKE_OFFER_COMPUTE_UNIT_LIMIT`] each.
+#[allow(dead_code)]
+pub fn run_many_offers_check(count: usize) -> Result<(), tester::CaseError> {
+    let times_count = |what: &str, value: u64| {
+        u64::try_from(count).ok().and_then(|count| value.checked_mul(count)).ok_or_else(|| {
+            to_case_error(TestContextError::ValidationError(format!(
+                "{} for {} offers overflows u64",
+                what, count
+            )))
+        })
+    };
+    let maker_balance_a = times_count("maker token A balance", DEFAULT_OFFERED_AMOUNT)?;
+    let compute_unit_limit = times_count("compute unit limit", MAKE_OFFER_COMPUTE_UNIT_LIMIT)?;
+
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::builder()
+        .maker_balance_a(maker_balance_a)
+        .build(&repo_path)
+        .map_err(to_case_error)?;
+
+    let mut total_compute_units: u64 = 0;
+    for offer_id in (DEFAULT_OFFER_ID..).take(count) {
+        let (offer, consumed) = fixture
+            .make_additional_offer_metered(offer_id, DEFAULT_OFFERED_AMOUNT, DEFAULT_WANTED_AMOUNT)
+            .map_err(to_case_error)?;
+        total_compute_units = total_compute_units.saturating_add(consumed);
+
+        let offer_data = offer_data_from_account(&fixture.get_account(&offer)?)?;
+        ensure_eq("offer id", offer_id, offer_data.id)?;
+        ensure_eq("offer maker", fixture.maker, offer_data.maker)?;
+        ensure_eq(
+            "offer token_b_wanted_amount",
+            DEFAULT_WANTED_AMOUNT,
+            offer_data.token_b_wanted_amount,
+        )?;
+    }
+
+    let program_accounts = fixture.context.accounts_owned_by(&fixture.program_id);
+    ensure_eq("accounts owned by the program", count, program_accounts.len())?;
+
+    if total_compute_units > compute_unit_limit {
+        return Err(to_case_error(TestContextError::ValidationError(format!(
+            "{} offers consumed {} compute units, exceeding the limit of {}",
+            count, total_compute_units, compute_unit_limit
+        ))));
     }
 
     Ok(())
 }
 
+/// Make the same offer twice and require the second `make_offer` to fail
+/// because the offer PDA already exists.
+///
+/// The maker is funded for both offers, so the second one cannot fail for
+/// lack of token A instead.
+pub fn run_duplicate_offer_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::builder()
+        .maker_balance_a(DEFAULT_OFFERED_AMOUNT * 2)
+        .build(&repo_path)
+        .map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    let instruction = fixture.make_offer_instruction();
+    expect_rejected(&mut fixture, &instruction, "second make_offer with the same offer id accepted")
+}
+
+pub fn run_make_offer_checks() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    let balances = fixture.balances()?;
+    ensure_eq("maker token A balance", 0, balances.maker_a)?;
+    ensure_eq("vault balance", fixture.expected_vault_amount(), balances.vault_a)?;
+
+    Ok(())
+}
+
+/// Run the full swap and require the taker to have paid exactly the wanted
+/// amount of token B and the vault to have been emptied and closed.
 pub fn run_take_offer_checks() -> Result<(), tester::CaseError> {
-    run_token_transfer_check()
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    let outcome = fixture.run_full_swap()?;
+
+    let taker_paid = outcome.before.taker_b.checked_sub(outcome.after.taker_b);
+    if taker_paid != Some(fixture.wanted_amount) {
+        return Err(to_case_error(TestContextError::mismatch(
+            "taker token B paid",
+            fixture.wanted_amount,
+            taker_paid.map_or_else(
+                || format!("a credit of {}", outcome.after.taker_b - outcome.before.taker_b),
+                |paid| paid.to_string(),
+            ),
+        )));
+    }
+    ensure_eq("vault balance after take_offer", 0, outcome.after.vault_a)?;
+    if !outcome.vault_closed {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Vault account was not closed after take_offer".to_string(),
+        )));
+    }
+
+    Ok(())
 }
 
 pub fn run_pda_checks() -> Result<(), tester::CaseError> {
@@ -746,17 +2082,30 @@ pub fn run_pda_checks() -> Result<(), tester::CaseError> {
     let offer_account = fixture.get_account(&fixture.offer)?;
     let offer = offer_data_from_account(&offer_account).map_err(to_case_error_from_context)?;
 
-    let (expected_offer, bump) = Pubkey::find_program_address(
-        &[OFFER_SEED_PREFIX, fixture.maker.as_ref(), &fixture.offer_id.to_le_bytes()],
+    let (expected_offer, bump) = find_offer_address(
+        fixture.offer_seeds,
+        &fixture.maker,
+        fixture.offer_id,
         &fixture.program_id,
     );
 
-    if expected_offer != fixture.offer || offer.bump != bump {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Offer PDA derivation mismatch",
-        )) as Box<dyn std::error::Error + Send + Sync>);
+    ensure_eq("offer PDA", expected_offer, fixture.offer)?;
+
+    // The stored bump must re-derive the offer address on its own, not just
+    // equal the canonical bump by value
+    let mut seeds = (fixture.offer_seeds)(&fixture.maker, fixture.offer_id);
+    seeds.push(vec![offer.bump]);
+    let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+    match Pubkey::create_program_address(&seeds, &fixture.program_id) {
+        Ok(address) => ensure_eq("offer PDA from stored bump", fixture.offer, address)?,
+        Err(err) => {
+            return Err(to_case_error(TestContextError::ValidationError(format!(
+                "Stored offer bump {} does not derive a valid PDA: {}",
+                offer.bump, err
+            ))));
+        }
     }
+    ensure_eq("offer bump", bump, offer.bump)?;
 
     Ok(())
 }
@@ -770,30 +2119,222 @@ pub fn run_vault_checks() -> Result<(), tester::CaseError> {
     let vault_owner = token_account_owner(&vault_account).map_err(to_case_error_from_context)?;
     let vault_mint = token_account_mint(&vault_account).map_err(to_case_error_from_context)?;
 
-    if vault_owner != fixture.offer || vault_mint != fixture.token_mint_a {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Vault ATA ownership or mint mismatch",
-        )) as Box<dyn std::error::Error + Send + Sync>);
+    ensure_eq("vault owner", fixture.offer, vault_owner)?;
+    ensure_eq("vault mint", fixture.token_mint_a, vault_mint)?;
+
+    Ok(())
+}
+
+/// Require the offer PDA and vault created by `make_offer` to be rent exempt.
+///
+/// The check runs under the default rent and again with the per-byte rate
+/// doubled, so a program that hardcodes the default rent instead of reading
+/// the sysvar under-funds its accounts in the second run.
+pub fn run_rent_exemption_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let default_rent = Rent::default();
+    let doubled_rent =
+        Rent { lamports_per_byte_year: default_rent.lamports_per_byte_year * 2, ..default_rent };
+
+    for rent in [default_rent, doubled_rent] {
+        let mut fixture =
+            SwapFixture::builder().rent(rent).build(&repo_path).map_err(to_case_error)?;
+        make_offer_success(&mut fixture).map_err(to_case_error)?;
+
+        for (name, pubkey) in [("Offer", fixture.offer), ("Vault", fixture.vault)] {
+            if !fixture.context.is_rent_exempt(&pubkey) {
+                return Err(to_case_error(TestContextError::ValidationError(format!(
+                    "{} account {} is not rent exempt after make_offer with {} lamports per \
+                     byte-year",
+                    name, pubkey, rent.lamports_per_byte_year
+                ))));
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Prove the program signs for the vault as the offer PDA.
+///
+/// A full swap must debit the vault into the taker's token A account, which
+/// only succeeds if the program signed the transfer with the offer's seeds.
+/// A second offer then has its stored bump tampered with before
+/// `take_offer`, which must be rejected since the seeds no longer derive the
+/// offer address.
+pub fn run_vault_authority_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+    let vault_before = fixture.balances()?.vault_a;
+    ensure_eq("vault balance after make_offer", fixture.expected_vault_amount(), vault_before)?;
+    take_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    let balances = fixture.balances()?;
+    ensure_eq("vault balance after take_offer", 0, balances.vault_a)?;
+    ensure_eq("taker token A balance", fixture.amount_after_fee_a(vault_before), balances.taker_a)?;
+
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+    let mut offer_account = fixture.get_account(&fixture.offer)?;
+    if offer_account.data.len() <= OFFER_BUMP_OFFSET {
+        return Err(to_case_error(TestContextError::data_too_short(
+            "Offer account",
+            &offer_account.data,
+            OFFER_BUMP_OFFSET + 1,
+        )));
+    }
+    offer_account.data[OFFER_BUMP_OFFSET] = offer_account.data[OFFER_BUMP_OFFSET].wrapping_sub(1);
+
+    let instruction = fixture.take_offer_instruction();
+    let overrides = [(fixture.offer, offer_account)];
+    expect_rejected_with_overrides(
+        &mut fixture,
+        &instruction,
+        &overrides,
+        "take_offer accepted with a tampered offer bump",
+    )
+}
+
 pub fn run_security_checks() -> Result<(), tester::CaseError> {
     let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
     let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
     make_offer_success(&mut fixture).map_err(to_case_error)?;
 
+    // A taker posing as the maker
     let mut bad_instruction = fixture.take_offer_instruction();
     bad_instruction.accounts[1] = AccountMeta::new(fixture.taker, false);
+    expect_rejected(&mut fixture, &bad_instruction, "invalid maker accepted")?;
+
+    // Token A passed off as the wanted token B
+    let mut bad_instruction = fixture.take_offer_instruction();
+    bad_instruction.accounts[3] = AccountMeta::new_readonly(fixture.token_mint_a, false);
+    expect_rejected(&mut fixture, &bad_instruction, "invalid token B mint accepted")
+}
+
+/// Require `make_offer` and `take_offer` to be rejected whenever one of their
+/// signer accounts has not signed.
+///
+/// Each account the tester marks as a signer (the maker for `make_offer`, the
+/// taker for `take_offer`) is in turn passed without its signature, so a
+/// missing `Signer` constraint on any of them is caught, and the failure names
+/// the account that was not enforced.
+pub fn run_wrong_signer_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+
+    let instruction = fixture.make_offer_instruction();
+    expect_signers_enforced(&mut fixture, "make_offer", &instruction)?;
+
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+    let instruction = fixture.take_offer_instruction();
+    expect_signers_enforced(&mut fixture, "take_offer", &instruction)
+}
+
+/// Run `instruction` once per signer account with that account's signature
+/// dropped, requiring every run to be rejected.
+fn expect_signers_enforced(
+    fixture: &mut SwapFixture,
+    name: &str,
+    instruction: &Instruction,
+) -> Result<(), tester::CaseError> {
+    for (index, meta) in instruction.accounts.iter().enumerate() {
+        if !meta.is_signer {
+            continue;
+        }
+        let mut unsigned = instruction.clone();
+        unsigned.accounts[index].is_signer = false;
+        expect_rejected(
+            fixture,
+            &unsigned,
+            &format!(
+                "{} accepted without a signature from account {} ({})",
+                name, index, meta.pubkey
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// Take an offer whose maker has no token B account yet and require
+/// `take_offer` to create it and pay the wanted amount into it.
+pub fn run_maker_account_init_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::builder()
+        .omit_maker_token_account_b()
+        .build(&repo_path)
+        .map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+    take_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    let maker_token_b = fixture.get_account(&fixture.maker_token_account_b)?;
+    ensure_eq("maker token B account owner", fixture.token_program, maker_token_b.owner)?;
+    let maker_owner = token_account_owner(&maker_token_b).map_err(to_case_error_from_context)?;
+    ensure_eq("maker token B account authority", fixture.maker, maker_owner)?;
+    let maker_amount = token_account_amount(&maker_token_b).map_err(to_case_error_from_context)?;
+    ensure_eq("maker token B balance", fixture.wanted_amount, maker_amount)?;
+
+    Ok(())
+}
+
+/// Take the same offer twice and require the second attempt to fail.
+///
+/// The first take closes the offer and vault, so a program that re-validates
+/// its accounts has nothing left to pay out from.
+pub fn run_double_take_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    make_offer_success(&mut fixture).map_err(to_case_error)?;
+    take_offer_success(&mut fixture).map_err(to_case_error)?;
+
+    if !fixture.context.is_closed(&fixture.offer) {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Offer account was not closed after take_offer".to_string(),
+        )));
+    }
+
+    let instruction = fixture.take_offer_instruction();
+    expect_rejected(&mut fixture, &instruction, "second take_offer of a closed offer accepted")
+}
+
+/// Pass a token account that is not the maker's ATA to `make_offer` and
+/// require the program to reject it.
+pub fn run_account_validation_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+
+    let bogus_account =
+        fixture.add_raw_token_account(fixture.token_mint_a, fixture.maker, fixture.offered_amount);
+    let mut bad_instruction = fixture.make_offer_instruction();
+    bad_instruction.accounts[3] = AccountMeta::new(bogus_account, false);
+
+    expect_rejected(&mut fixture, &bad_instruction, "non-ATA maker token account accepted")
+}
+
+fn expect_rejected(
+    fixture: &mut SwapFixture,
+    instruction: &Instruction,
+    failure: &str,
+) -> Result<(), tester::CaseError> {
+    expect_rejected_with_overrides(fixture, instruction, &[], failure)
+}
 
-    match fixture.context.execute_instruction(&bad_instruction) {
-        Ok(()) => Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Security check failed: invalid maker accepted",
-        )) as Box<dyn std::error::Error + Send + Sync>),
+/// Require an instruction to fail with an error return when run with some
+/// accounts substituted. An abort is reported as a failure, since the program
+/// should return an error instead of panicking. The fixture's state is
+/// unchanged either way.
+fn expect_rejected_with_overrides(
+    fixture: &mut SwapFixture,
+    instruction: &Instruction,
+    overrides: &[(Pubkey, Account)],
+    failure: &str,
+) -> Result<(), tester::CaseError> {
+    match fixture.context.execute_instruction_with_overrides(instruction, overrides) {
+        Ok(()) => Err(to_case_error(TestContextError::expected_failure(failure))),
         Err(TestContextError::ExecutionError(_)) => Ok(()),
+        Err(TestContextError::ProgramAborted(msg)) => {
+            Err(to_case_error(TestContextError::aborted_instead_of_error(msg)))
+        }
         Err(err) => Err(to_case_error(err)),
     }
 }
@@ -810,41 +2351,201 @@ pub fn run_error_checks() -> Result<(), tester::CaseError> {
     )
     .map_err(to_case_error)?;
 
-    match fixture.execute_make_offer() {
-        Ok(()) => Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Expected make_offer to fail with insufficient funds",
-        )) as Box<dyn std::error::Error + Send + Sync>),
-        Err(TestContextError::ExecutionError(_)) => Ok(()),
-        Err(err) => Err(to_case_error(err)),
+    // The maker is deliberately unfunded; anything else is a fixture bug
+    if fixture.validate_preconditions().is_ok() {
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Fixture funds the maker, so an insufficient-funds rejection cannot be tested"
+                .to_string(),
+        )));
     }
+
+    // The vault transfer fails inside the token program, whose error the
+    // program should propagate rather than mask with a failure of its own
+    let instruction = fixture.make_offer_instruction();
+    fixture
+        .context
+        .expect_custom_error(&instruction, TokenError::InsufficientFunds as u32)
+        .map_err(to_case_error)
+}
+
+/// Require `make_offer` to reject a zero wanted amount and a zero offered
+/// amount.
+///
+/// The token program itself accepts zero-amount transfers, so neither case is
+/// rejected unless the program adds its own guard (e.g. `require!(amount > 0)`).
+/// The plain Anchor swap example this course is modelled on has no such guard
+/// and accepts both, which is why this check is not wired to a stage; graders
+/// who enable it should expect only programs with explicit amount validation
+/// to pass.
+#[allow(dead_code)]
+pub fn run_zero_amount_checks() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+
+    let zero_amounts = [
+        (DEFAULT_OFFERED_AMOUNT, 0, "wanted_amount"),
+        (0, DEFAULT_WANTED_AMOUNT, "offered_amount"),
+    ];
+    for (offered_amount, wanted_amount, field) in zero_amounts {
+        let mut fixture = SwapFixture::builder()
+            .offered_amount(offered_amount)
+            .wanted_amount(wanted_amount)
+            .build(&repo_path)
+            .map_err(to_case_error)?;
+        let instruction = fixture.make_offer_instruction();
+        expect_rejected(
+            &mut fixture,
+            &instruction,
+            &format!("make_offer with zero {} accepted", field),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Send a `make_offer` whose data is missing the trailing `wanted_amount`
+/// and require the program to reject it.
+pub fn run_malformed_instruction_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+
+    let mut data = build_make_offer_data(
+        &fixture.discriminator("make_offer"),
+        fixture.offer_id,
+        fixture.offered_amount,
+        fixture.wanted_amount,
+    );
+    data.truncate(data.len() - std::mem::size_of::<u64>());
+
+    let instruction = fixture.make_offer_instruction_with_data(data);
+    expect_rejected(&mut fixture, &instruction, "make_offer with truncated data accepted")
+}
+
+/// Require `make_offer` to fail when the maker's token A account is frozen.
+#[allow(dead_code)]
+pub fn run_frozen_account_check() -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::builder()
+        .freeze_maker_token_account_a()
+        .build(&repo_path)
+        .map_err(to_case_error)?;
+
+    let instruction = fixture.make_offer_instruction();
+    expect_rejected(
+        &mut fixture,
+        &instruction,
+        "make_offer with a frozen maker token account accepted",
+    )
 }
 
 pub fn run_cpi_checks() -> Result<(), tester::CaseError> {
     run_cpi_transfer_check()
 }
 
+/// Run the full swap and require it to conserve both mints and to close the
+/// offer and vault.
 pub fn run_testing_checks() -> Result<(), tester::CaseError> {
-    run_token_transfer_check()
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    let outcome = fixture.run_full_swap()?;
+
+    ensure_eq("total token A supply", outcome.before.total_a(), outcome.after.total_a())?;
+    ensure_eq("total token B supply", outcome.before.total_b(), outcome.after.total_b())?;
+    if !outcome.offer_closed || !outcome.vault_closed {
+        return Err(to_case_error(TestContextError::ValidationError(format!(
+            "Expected the offer and vault to be closed after take_offer (offer closed: {}, vault \
+             closed: {})",
+            outcome.offer_closed, outcome.vault_closed
+        ))));
+    }
+
+    Ok(())
 }
 
 pub fn run_deployment_checks() -> Result<(), tester::CaseError> {
     let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
     let program_id = load_swap_program_id(&repo_path).map_err(to_case_error_from_load)?;
     if program_id == Pubkey::default() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Program ID is still default",
-        )) as Box<dyn std::error::Error + Send + Sync>);
+        return Err(to_case_error(TestContextError::ValidationError(
+            "Program ID is still default".to_string(),
+        )));
     }
     run_make_offer_smoke(&repo_path)
 }
 
+/// Run `make_offer` and fail if it consumes more than `max_compute_units`.
+pub fn run_compute_budget_check(max_compute_units: u64) -> Result<(), tester::CaseError> {
+    let repo_path = get_repo_dir().map_err(to_case_error_from_load)?;
+    let mut fixture = SwapFixture::new_default(&repo_path).map_err(to_case_error)?;
+    let consumed = fixture.execute_make_offer_metered().map_err(to_case_error)?;
+
+    if consumed > max_compute_units {
+        return Err(to_case_error(TestContextError::ValidationError(format!(
+            "make_offer consumed {} compute units, exceeding the limit of {}",
+            consumed, max_compute_units
+        ))));
+    }
+
+    Ok(())
+}
+
 fn run_make_offer_smoke(repo_path: &Path) -> Result<(), tester::CaseError> {
     let mut fixture = SwapFixture::new_default(repo_path).map_err(to_case_error)?;
     match fixture.execute_make_offer() {
         Ok(()) => Ok(()),
-        Err(TestContextError::ExecutionError(_)) => Ok(()),
+        Err(TestContextError::ExecutionError(_) | TestContextError::ProgramAborted(_)) => Ok(()),
         Err(err) => Err(to_case_error(err)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn canonical_repo_dir_resolves_symlinks() {
+        let base = std::env::temp_dir().join(format!("swap-tester-repo-{}", std::process::id()));
+        let repo = base.join("repo");
+        let link = base.join("link");
+        std::fs::create_dir_all(&repo).unwrap();
+        std::os::unix::fs::symlink(&repo, &link).unwrap();
+
+        let resolved = canonical_repo_dir(link.join("../link").to_str().unwrap());
+        let expected = std::fs::canonicalize(&repo).unwrap();
+        std::fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(resolved, Ok(expected));
+    }
+
+    #[test]
+    fn builder_rejects_transfer_fee_above_100_percent() {
+        let result = SwapFixture::builder()
+            .transfer_fee_a(10_001, TRANSFER_FEE_MAXIMUM)
+            .build(Path::new("/nonexistent/swap-tester-repo"));
+
+        assert!(
+            matches!(result, Err(TestContextError::ValidationError(msg)) if msg.contains("10001"))
+        );
+    }
+
+    #[test]
+    fn builder_rejects_overflowing_shared_mint_supply() {
+        let result = SwapFixture::builder()
+            .same_mint()
+            .maker_balance_a(u64::MAX)
+            .taker_balance_b(1)
+            .build(Path::new("/nonexistent/swap-tester-repo"));
+
+        assert!(
+            matches!(result, Err(TestContextError::ValidationError(msg)) if msg.contains("overflows"))
+        );
+    }
+
+    #[test]
+    fn canonical_repo_dir_reports_missing_dir() {
+        let missing = "/nonexistent/swap-tester-repo";
+        let err = canonical_repo_dir(missing).map_err(ProgramLoadError::RepoNotFound).unwrap_err();
+
+        assert_eq!(err.to_string(), format!("Repository directory not found: {}", missing));
+    }
+}
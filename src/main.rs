@@ -12,9 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod debug;
 mod definition;
 mod helpers;
 mod mollusk;
+mod report;
 mod stages;
 
 use std::process::ExitCode;
@@ -0,0 +1,92 @@
+// Copyright (c) The StackClass Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Token-2022 extension layout shared by the fixture and the test context.
+//!
+//! An account with extensions stores its account type byte right after the
+//! base token account length, then each extension as a little-endian `u16`
+//! type, a `u16` length and its value.
+
+use solana_account::Account;
+use solana_program_pack::Pack;
+use spl_token_interface::state::Account as TokenAccount;
+
+use crate::mollusk::TestContextError;
+
+/// Offset of the account type byte, shared by mints and token accounts.
+pub const ACCOUNT_TYPE_OFFSET: usize = TokenAccount::LEN;
+/// Account type byte of a mint.
+pub const ACCOUNT_TYPE_MINT: u8 = 1;
+/// Account type byte of a token account.
+pub const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+/// Append Token-2022 extensions to a base mint or token account.
+///
+/// The data is padded to [`ACCOUNT_TYPE_OFFSET`], the account type byte is
+/// written, and each extension follows as a type-length-value entry, so the
+/// buffer is sized exactly as Token-2022 expects. An account is returned
+/// unchanged when `extensions` is empty. The lamports are left for the caller
+/// to set when the account is added.
+///
+/// # Arguments
+///
+/// * `account` - The account holding the base layout
+/// * `account_type` - [`ACCOUNT_TYPE_MINT`] or [`ACCOUNT_TYPE_ACCOUNT`]
+/// * `extensions` - `(extension type, value)` pairs, in order
+///
+/// # Returns
+///
+/// * `Ok(Account)` - The account with its extensions
+/// * `Err(TestContextError)` - If an extension value is too long for its `u16` length
+pub fn with_extensions<V: AsRef<[u8]>>(
+    mut account: Account,
+    account_type: u8,
+    extensions: &[(u16, V)],
+) -> Result<Account, TestContextError> {
+    if extensions.is_empty() {
+        return Ok(account);
+    }
+
+    let data = &mut account.data;
+    data.resize(ACCOUNT_TYPE_OFFSET, 0);
+    data.push(account_type);
+    for (extension_type, value) in extensions {
+        let value = value.as_ref();
+        let len = u16::try_from(value.len()).map_err(|_| {
+            TestContextError::ValidationError(format!(
+                "extension {} value of {} bytes exceeds the maximum of {}",
+                extension_type,
+                value.len(),
+                u16::MAX
+            ))
+        })?;
+        data.extend_from_slice(&extension_type.to_le_bytes());
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(value);
+    }
+    Ok(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_extensions_rejects_oversized_value() {
+        let value = vec![0u8; usize::from(u16::MAX) + 1];
+        let result = with_extensions(Account::default(), ACCOUNT_TYPE_ACCOUNT, &[(2, value)]);
+
+        assert!(matches!(result, Err(TestContextError::ValidationError(_))));
+    }
+}
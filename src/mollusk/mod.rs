@@ -18,16 +18,25 @@
 //! testing of the swap program. It handles program loading, account setup,
 //! and instruction execution.
 
+pub mod extensions;
 pub mod program_loader;
 pub mod test_context;
 
-pub use program_loader::{ProgramLoadError, load_swap_program, load_swap_program_id};
-pub use test_context::{SwapTestContext, TestContextError};
+pub use program_loader::{
+    ProgramLoadError, load_dependency_program, load_idl_instruction_accounts,
+    load_idl_instruction_discriminator, load_idl_instructions, load_program_elf, load_swap_program,
+    load_swap_program_id, program_build_id,
+};
+pub use test_context::{AccountSnapshot, ResultCheck, SwapTestContext, TestContextError};
 
-use mollusk_svm::Mollusk;
+use mollusk_svm::{Mollusk, program::loader_keys};
+use mollusk_svm_programs_token::{associated_token, token, token2022};
 use solana_pubkey::Pubkey;
 use std::path::Path;
 
+/// Token programs registered when the caller does not ask for others.
+pub const DEFAULT_TOKEN_PROGRAMS: &[Pubkey] = &[token::ID];
+
 /// Create a new Mollusk instance for testing the swap program.
 ///
 /// This function attempts to load the compiled swap program from the
@@ -43,40 +52,128 @@ use std::path::Path;
 ///
 /// * `Ok(Mollusk)` - A configured Mollusk instance
 /// * `Err(ProgramLoadError)` - If the program cannot be loaded
+#[allow(dead_code)]
 pub fn create_swap_mollusk(
     repo_dir: &Path,
     program_id: &Pubkey,
 ) -> Result<Mollusk, ProgramLoadError> {
-    let program_path = load_swap_program(repo_dir)?;
-    let program_name = program_path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("swap");
+    create_swap_mollusk_with_programs(repo_dir, program_id, DEFAULT_TOKEN_PROGRAMS)
+}
 
-    let program_dir = program_path
-        .parent()
-        .ok_or_else(|| ProgramLoadError::ProgramDirNotFound(program_path.clone()))?;
+/// Create a new Mollusk instance with a chosen set of token programs.
+///
+/// This behaves like [`create_swap_mollusk`], but registers each token
+/// program in `token_programs` so CPIs into it resolve. Pass the Token-2022
+/// program id to test mints owned by it.
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+/// * `program_id` - The swap program ID
+/// * `token_programs` - The token program IDs to register
+///
+/// # Returns
+///
+/// * `Ok(Mollusk)` - A configured Mollusk instance
+/// * `Err(ProgramLoadError)` - If the program cannot be loaded or a token program is unsupported
+pub fn create_swap_mollusk_with_programs(
+    repo_dir: &Path,
+    program_id: &Pubkey,
+    token_programs: &[Pubkey],
+) -> Result<Mollusk, ProgramLoadError> {
+    let program_path = load_swap_program(repo_dir)?;
+    load_swap_mollusk(&program_path, program_id, token_programs).map(|(mollusk, _)| mollusk)
+}
 
-    // SAFETY: set_var is process-global; we set it once before loading the ELF.
-    unsafe {
-        std::env::set_var("SBF_OUT_DIR", program_dir);
-    }
+/// Create a Mollusk instance running the program at `program_path`, and read
+/// the program's build ID.
+fn load_swap_mollusk(
+    program_path: &Path,
+    program_id: &Pubkey,
+    token_programs: &[Pubkey],
+) -> Result<(Mollusk, Option<String>), ProgramLoadError> {
+    let elf = load_program_elf(program_path)?;
+    let build_id = program_build_id(program_path);
+    crate::debug::log(format_args!(
+        "loaded {} (build id {})",
+        program_path.display(),
+        build_id.as_deref().unwrap_or("none")
+    ));
 
-    let mut mollusk = Mollusk::new(program_id, program_name);
+    // Register the ELF bytes directly instead of pointing Mollusk at
+    // SBF_OUT_DIR, which is process-global
+    let mut mollusk = Mollusk::default();
+    mollusk.add_program_with_elf_and_loader(program_id, &elf, &loader_keys::LOADER_V3);
 
     // Add necessary programs for testing
-    add_required_programs(&mut mollusk);
+    add_required_programs(&mut mollusk, token_programs)?;
+
+    Ok((mollusk, build_id))
+}
+
+/// Create a new Mollusk instance that also loads the swap program's
+/// dependencies.
+///
+/// Each dependency is located by name with [`load_dependency_program`] and
+/// registered under its program ID, so CPIs from the swap program into it
+/// resolve.
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+/// * `program_id` - The swap program ID
+/// * `deps` - The ID and library name of each dependency program
+///
+/// # Returns
+///
+/// * `Ok(Mollusk)` - A configured Mollusk instance
+/// * `Err(ProgramLoadError)` - If the swap program or a dependency cannot be loaded
+#[allow(dead_code)]
+pub fn create_swap_mollusk_with_deps(
+    repo_dir: &Path,
+    program_id: &Pubkey,
+    deps: &[(Pubkey, &str)],
+) -> Result<Mollusk, ProgramLoadError> {
+    let mut mollusk = create_swap_mollusk(repo_dir, program_id)?;
+
+    for (dep_id, dep_name) in deps {
+        let elf = load_program_elf(&load_dependency_program(repo_dir, dep_name)?)?;
+        mollusk.add_program_with_elf_and_loader(dep_id, &elf, &loader_keys::LOADER_V3);
+    }
 
     Ok(mollusk)
 }
 
+/// Format bytes as lowercase hex, e.g. for discriminators and build IDs.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 /// Add required programs to the Mollusk instance.
 ///
 /// This includes system programs and SPL Token programs that are commonly
 /// used in swap operations.
-fn add_required_programs(mollusk: &mut Mollusk) {
+fn add_required_programs(
+    mollusk: &mut Mollusk,
+    token_programs: &[Pubkey],
+) -> Result<(), ProgramLoadError> {
     // System program is already included by default in Mollusk
 
-    // SPL Token program and Associated Token program - needed for token operations
-    mollusk_svm_programs_token::token::add_program(mollusk);
-    mollusk_svm_programs_token::associated_token::add_program(mollusk);
+    // SPL Token programs - needed for token operations
+    for token_program in token_programs {
+        if *token_program == token::ID {
+            token::add_program(mollusk);
+        } else if *token_program == token2022::ID {
+            token2022::add_program(mollusk);
+        } else {
+            return Err(ProgramLoadError::UnsupportedTokenProgram(*token_program));
+        }
+    }
+
+    // Associated Token program - shared by every token program
+    associated_token::add_program(mollusk);
+
+    Ok(())
 }
 
 /// Initialize a test context with the swap program.
@@ -92,8 +189,35 @@ fn add_required_programs(mollusk: &mut Mollusk) {
 ///
 /// * `Ok(SwapTestContext)` - A configured test context
 /// * `Err(TestContextError)` - If initialization fails
+#[allow(dead_code)]
 pub fn init_test_context(repo_dir: &Path) -> Result<SwapTestContext, TestContextError> {
+    init_test_context_with_programs(repo_dir, DEFAULT_TOKEN_PROGRAMS)
+}
+
+/// Initialize a test context with a chosen set of token programs.
+///
+/// The build ID of the loaded program binary is recorded on the context (see
+/// [`SwapTestContext::program_build_id`]), as are the instructions declared in
+/// the program's IDL, which failure messages compare against.
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+/// * `token_programs` - The token program IDs to register
+///
+/// # Returns
+///
+/// * `Ok(SwapTestContext)` - A configured test context
+/// * `Err(TestContextError)` - If initialization fails
+pub fn init_test_context_with_programs(
+    repo_dir: &Path,
+    token_programs: &[Pubkey],
+) -> Result<SwapTestContext, TestContextError> {
     let program_id = load_swap_program_id(repo_dir)?;
-    let mollusk = create_swap_mollusk(repo_dir, &program_id)?;
-    SwapTestContext::new(mollusk, program_id)
+    let program_path = load_swap_program(repo_dir)?;
+    let (mollusk, build_id) = load_swap_mollusk(&program_path, &program_id, token_programs)?;
+    let mut context = SwapTestContext::new(mollusk, program_id)?;
+    context.set_program_build_id(build_id);
+    context.set_idl_instructions(load_idl_instructions(repo_dir).unwrap_or_default());
+    Ok(context)
 }
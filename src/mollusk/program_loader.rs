@@ -14,24 +14,37 @@
 
 //! Program loader module for loading the swap program from disk.
 
-use mollusk_svm::file;
 use solana_pubkey::Pubkey;
 use std::{
+    ffi::OsStr,
+    fs::File,
+    io::Read,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
+    time::SystemTime,
 };
 
 /// Error type for program loading operations.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ProgramLoadError {
     RepoNotFound(PathBuf),
     AnchorTomlNotFound(PathBuf),
     ProgramIdNotFound,
     InvalidProgramId(String),
-    ProgramDirNotFound(PathBuf),
-    ProgramNotFound,
-    IoError(std::io::Error),
+    ProgramIdMismatch {
+        configured: Pubkey,
+        keypair: Pubkey,
+    },
     #[allow(dead_code)]
+    ProgramDirNotFound(PathBuf),
+    /// The program SO file was not found; holds every location searched.
+    ProgramNotFound(Vec<PathBuf>),
+    /// Several program crates could be the swap program; holds their names.
+    AmbiguousProgram(Vec<String>),
+    UnsupportedTokenProgram(Pubkey),
+    /// Shared so the error stays cloneable, since `io::Error` is not.
+    IoError(Arc<std::io::Error>),
     ElfLoadError(String),
 }
 
@@ -45,16 +58,35 @@ impl std::fmt::Display for ProgramLoadError {
                 write!(f, "Anchor.toml not found: {}", path.display())
             }
             ProgramLoadError::ProgramIdNotFound => {
-                write!(f, "Program ID not found in Anchor.toml")
+                write!(f, "Program ID not found in Anchor.toml or declare_id!")
             }
             ProgramLoadError::InvalidProgramId(value) => {
                 write!(f, "Invalid program ID in Anchor.toml: {}", value)
             }
+            ProgramLoadError::ProgramIdMismatch { configured, keypair } => write!(
+                f,
+                "Program ID {} in Anchor.toml does not match the deploy keypair {}; run `anchor \
+                 keys sync`",
+                configured, keypair
+            ),
             ProgramLoadError::ProgramDirNotFound(path) => {
                 write!(f, "Program directory not found: {}", path.display())
             }
-            ProgramLoadError::ProgramNotFound => {
-                write!(f, "Program SO file not found in any of the expected locations")
+            ProgramLoadError::ProgramNotFound(searched) => {
+                write!(f, "Program SO file not found; searched:")?;
+                for path in searched {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
+            }
+            ProgramLoadError::AmbiguousProgram(names) => write!(
+                f,
+                "Cannot tell which program is the swap program among {}; include `swap` in the \
+                 swap program's crate name",
+                names.join(", ")
+            ),
+            ProgramLoadError::UnsupportedTokenProgram(program_id) => {
+                write!(f, "Unsupported token program: {}", program_id)
             }
             ProgramLoadError::IoError(err) => write!(f, "Failed to read program file: {}", err),
             ProgramLoadError::ElfLoadError(msg) => write!(f, "Failed to load program ELF: {}", msg),
@@ -65,7 +97,7 @@ impl std::fmt::Display for ProgramLoadError {
 impl std::error::Error for ProgramLoadError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            ProgramLoadError::IoError(err) => Some(err),
+            ProgramLoadError::IoError(err) => Some(err.as_ref()),
             _ => None,
         }
     }
@@ -73,62 +105,417 @@ impl std::error::Error for ProgramLoadError {
 
 impl From<std::io::Error> for ProgramLoadError {
     fn from(err: std::io::Error) -> Self {
-        ProgramLoadError::IoError(err)
+        ProgramLoadError::IoError(Arc::new(err))
     }
 }
 
+/// Environment variable that points directly at the program SO file.
+pub const PROGRAM_SO_ENV: &str = "STACKCLASS_PROGRAM_SO";
+
+/// Program name used when none can be read from the repository.
+pub const DEFAULT_PROGRAM_NAME: &str = "swap_program";
+
+/// Find the Anchor workspace root containing a directory.
+///
+/// Walks up from `dir` to the first ancestor (including `dir` itself) that
+/// holds an `Anchor.toml`, so a repository pointed at `programs/<name>` still
+/// resolves to the workspace where `target/` lives.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to start from
+///
+/// # Returns
+///
+/// * `Some(PathBuf)` - The workspace root
+/// * `None` - If no ancestor holds an `Anchor.toml`
+pub fn find_workspace_root(dir: &Path) -> Option<PathBuf> {
+    let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors().find(|ancestor| ancestor.join("Anchor.toml").is_file()).map(Path::to_path_buf)
+}
+
+/// The workspace root for `repo_dir`, or `repo_dir` itself outside a workspace.
+fn workspace_root(repo_dir: &Path) -> PathBuf {
+    find_workspace_root(repo_dir).unwrap_or_else(|| repo_dir.to_path_buf())
+}
+
 /// Load the swap program from the user's repository directory.
 ///
+/// The program name is read from the program crate's `Cargo.toml` (see
+/// [`resolve_program_name`]) and the search is delegated to
+/// [`load_swap_program_named`]. Both run against the Anchor workspace root
+/// (see [`find_workspace_root`]).
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+///
+/// # Returns
+///
+/// * `Ok(PathBuf)` - Path to the program SO file
+/// * `Err(ProgramLoadError)` - If the program cannot be found or loaded
+pub fn load_swap_program(repo_dir: &Path) -> Result<PathBuf, ProgramLoadError> {
+    let root = workspace_root(repo_dir);
+    load_swap_program_named(&root, &resolve_program_name(&root)?)
+}
+
+/// Load a program with the given name from the user's repository directory.
+///
 /// This function searches for the compiled program SO file in the following
-/// locations (in order):
+/// locations of the workspace root (in order):
+///
+/// 1. `target/deploy/<program_name>.so`
+/// 2. `target/sbf-solana-solana/release/<program_name>.so`
+/// 3. `artifacts/<program_name>.so`
+///
+/// When [`PROGRAM_SO_ENV`] is set, its path is used instead and none of these
+/// locations are probed.
 ///
-/// 1. `repo_dir/target/deploy/swap.so`
-/// 2. `repo_dir/target/sbf-solana-solana/release/swap.so`
-/// 3. `repo_dir/artifacts/swap.so`
+/// The located file is checked with [`validate_sbf_elf`] before it is
+/// returned.
 ///
 /// # Arguments
 ///
 /// * `repo_dir` - Path to the user's repository directory
+/// * `program_name` - The program's library name, e.g. `swap_program`
 ///
 /// # Returns
 ///
 /// * `Ok(PathBuf)` - Path to the program SO file
 /// * `Err(ProgramLoadError)` - If the program cannot be found or loaded
-pub fn load_swap_program(repo_dir: &Path) -> Result<PathBuf, ProgramLoadError> {
-    if !repo_dir.exists() {
-        return Err(ProgramLoadError::RepoNotFound(repo_dir.to_path_buf()));
+pub fn load_swap_program_named(
+    repo_dir: &Path,
+    program_name: &str,
+) -> Result<PathBuf, ProgramLoadError> {
+    let program_path = match std::env::var_os(PROGRAM_SO_ENV) {
+        Some(path) => program_so_override(PathBuf::from(path))?,
+        None => find_program_so(&workspace_root(repo_dir), program_name)?,
+    };
+    validate_sbf_elf(&program_path)?;
+    crate::debug::log(format_args!(
+        "resolved program {} to {}",
+        program_name,
+        program_path.display()
+    ));
+    Ok(program_path)
+}
+
+/// Load a dependency program with the given name from the user's repository
+/// directory.
+///
+/// This searches the same locations as [`load_swap_program_named`], but
+/// ignores [`PROGRAM_SO_ENV`], which only overrides the swap program itself.
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+/// * `program_name` - The dependency's library name, e.g. `registry`
+///
+/// # Returns
+///
+/// * `Ok(PathBuf)` - Path to the program SO file
+/// * `Err(ProgramLoadError)` - If the program cannot be found or loaded
+pub fn load_dependency_program(
+    repo_dir: &Path,
+    program_name: &str,
+) -> Result<PathBuf, ProgramLoadError> {
+    let program_path = find_program_so(&workspace_root(repo_dir), program_name)?;
+    validate_sbf_elf(&program_path)?;
+    crate::debug::log(format_args!(
+        "resolved dependency {} to {}",
+        program_name,
+        program_path.display()
+    ));
+    Ok(program_path)
+}
+
+/// Check the path given by [`PROGRAM_SO_ENV`].
+fn program_so_override(path: PathBuf) -> Result<PathBuf, ProgramLoadError> {
+    if path.is_file() {
+        return Ok(path);
     }
 
-    // Try standard Anchor deployment path
-    let deploy_path = repo_dir.join("target/deploy/swap_program.so");
-    if deploy_path.exists() {
-        return Ok(deploy_path);
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() && !parent.is_dir() => {
+            Err(ProgramLoadError::RepoNotFound(parent.to_path_buf()))
+        }
+        _ => Err(ProgramLoadError::ProgramNotFound(vec![path])),
     }
+}
 
-    // Try SBF release path
-    let sbf_path = repo_dir.join("target/sbf-solana-solana/release/swap_program.so");
-    if sbf_path.exists() {
-        return Ok(sbf_path);
+/// Probe the standard locations for the program SO file.
+///
+/// The first existing location in search order is selected. If another
+/// location holds a newer binary, every candidate is listed in the debug trace
+/// so a stale artifact can be diagnosed.
+fn find_program_so(repo_dir: &Path, program_name: &str) -> Result<PathBuf, ProgramLoadError> {
+    if !repo_dir.exists() {
+        return Err(ProgramLoadError::RepoNotFound(repo_dir.to_path_buf()));
     }
 
-    // Try artifacts directory
-    let artifacts_path = repo_dir.join("artifacts/swap_program.so");
-    if artifacts_path.exists() {
-        return Ok(artifacts_path);
+    let file_name = format!("{}.so", program_name);
+
+    let probed: Vec<PathBuf> = [
+        // Standard Anchor deployment path
+        "target/deploy",
+        // SBF release path
+        "target/sbf-solana-solana/release",
+        // Artifacts directory
+        "artifacts",
+    ]
+    .iter()
+    .map(|dir| repo_dir.join(dir).join(&file_name))
+    .collect();
+    let candidates: Vec<PathBuf> = probed.iter().filter(|path| path.exists()).cloned().collect();
+
+    if let Some(selected) = candidates.first() {
+        warn_if_stale(selected, &candidates);
+        return Ok(selected.clone());
     }
 
     // Try to find any .so file in the target directory
-    if let Some(so_file) = find_so_file_in_target(repo_dir) {
+    if let Some(so_file) = find_so_file_in_target(repo_dir, program_name) {
         return Ok(so_file);
     }
 
-    Err(ProgramLoadError::ProgramNotFound)
+    let mut searched = probed;
+    searched.push(repo_dir.join("target/**/*.so"));
+    Err(ProgramLoadError::ProgramNotFound(searched))
+}
+
+/// Report in the debug trace when a candidate other than `selected` was
+/// modified more recently, listing every candidate with its age.
+fn warn_if_stale(selected: &Path, candidates: &[PathBuf]) {
+    let selected_time = modified_time(selected);
+    if candidates.iter().all(|candidate| modified_time(candidate) <= selected_time) {
+        return;
+    }
+
+    crate::debug::log(format_args!(
+        "{} is not the newest program binary found:",
+        selected.display()
+    ));
+    for candidate in candidates {
+        let age = modified_time(candidate)
+            .and_then(|time| time.elapsed().ok())
+            .map_or_else(|| "unknown age".to_string(), |age| format!("{}s old", age.as_secs()));
+        crate::debug::log(format_args!("  {} ({})", candidate.display(), age));
+    }
+}
+
+/// ELF machine type used by BPF programs.
+const EM_BPF: u16 = 247;
+/// ELF machine type used by SBF programs.
+const EM_SBF: u16 = 263;
+
+/// Validate that a file is a Solana SBF program.
+///
+/// This reads the ELF header and checks the magic, class, byte order and
+/// machine type, so a native shared library is rejected with a descriptive
+/// error instead of failing deep inside Mollusk.
+///
+/// # Arguments
+///
+/// * `path` - Path to the program SO file
+///
+/// # Returns
+///
+/// * `Ok(())` - If the file is a 64-bit little-endian BPF/SBF ELF
+/// * `Err(ProgramLoadError)` - If the file cannot be read or is not an SBF ELF
+pub fn validate_sbf_elf(path: &Path) -> Result<(), ProgramLoadError> {
+    let mut header = [0u8; 20];
+    File::open(path)?.read_exact(&mut header).map_err(|_| {
+        ProgramLoadError::ElfLoadError(format!("{} is too short to be an ELF", path.display()))
+    })?;
+
+    if header[0..4] != *b"\x7fELF" {
+        return Err(ProgramLoadError::ElfLoadError(format!(
+            "{} is not an ELF file",
+            path.display()
+        )));
+    }
+
+    // e_ident[EI_CLASS] == ELFCLASS64 and e_ident[EI_DATA] == ELFDATA2LSB
+    if header[4] != 2 || header[5] != 1 {
+        return Err(ProgramLoadError::ElfLoadError(format!(
+            "{} is not a 64-bit little-endian ELF",
+            path.display()
+        )));
+    }
+
+    let machine = u16::from_le_bytes([header[18], header[19]]);
+    if machine != EM_BPF && machine != EM_SBF {
+        return Err(ProgramLoadError::ElfLoadError(format!(
+            "{} targets ELF machine {} instead of BPF/SBF; build it with `anchor build` or \
+             `cargo build-sbf`",
+            path.display(),
+            machine
+        )));
+    }
+
+    Ok(())
+}
+
+/// Resolve the name of the compiled swap program.
+///
+/// Each `programs/*/Cargo.toml` names a crate, preferring `[lib] name` over
+/// the package name (with `-` replaced by `_`, as cargo does for library
+/// targets). When the workspace has several, only the crates Anchor.toml
+/// lists under a `[programs.*]` section are kept, and among those a crate
+/// whose name contains `swap` wins. Falls back to [`DEFAULT_PROGRAM_NAME`]
+/// when no manifest declares a name.
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+///
+/// # Returns
+///
+/// * `Ok(String)` - The program's library name
+/// * `Err(ProgramLoadError)` - If several crates remain candidates
+pub fn resolve_program_name(repo_dir: &Path) -> Result<String, ProgramLoadError> {
+    let root = workspace_root(repo_dir);
+    let mut names: Vec<String> = program_crates(&root).into_iter().map(|(_, name)| name).collect();
+
+    // Crates Anchor.toml does not deploy are libraries, not programs
+    if names.len() > 1 &&
+        let Ok(anchor) = std::fs::read_to_string(root.join("Anchor.toml"))
+    {
+        let keys = anchor_program_keys(&anchor);
+        if names.iter().any(|name| keys.contains(name)) {
+            names.retain(|name| keys.contains(name));
+        }
+    }
+
+    if names.len() > 1 && names.iter().any(|name| name.contains("swap")) {
+        names.retain(|name| name.contains("swap"));
+    }
+
+    match names.len() {
+        0 => Ok(DEFAULT_PROGRAM_NAME.to_string()),
+        1 => Ok(names.remove(0)),
+        _ => Err(ProgramLoadError::AmbiguousProgram(names)),
+    }
+}
+
+/// List the program names Anchor.toml assigns an address to in any
+/// `[programs.*]` section, with `-` replaced by `_`.
+fn anchor_program_keys(toml: &str) -> Vec<String> {
+    let mut section = "";
+    let mut keys = Vec::new();
+
+    for raw_line in toml.lines() {
+        let line = raw_line.trim();
+
+        if line.starts_with('[') && line.ends_with(']') {
+            section = &line[1..line.len() - 1];
+            continue;
+        }
+
+        let in_programs_section = section == "programs" || section.starts_with("programs.");
+        if !in_programs_section || line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some((key, _)) = line.split_once('=') {
+            let key = key.trim().replace('-', "_");
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+
+    keys
+}
+
+/// List the program crates under `programs/` as `(directory, library name)`,
+/// sorted by directory.
+fn program_crates(root: &Path) -> Vec<(PathBuf, String)> {
+    let Ok(entries) = std::fs::read_dir(root.join("programs")) else {
+        return Vec::new();
+    };
+
+    let mut dirs: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    dirs.sort();
+
+    dirs.into_iter()
+        .filter_map(|dir| {
+            let content = std::fs::read_to_string(dir.join("Cargo.toml")).ok()?;
+            find_lib_name(&content).map(|name| (dir, name))
+        })
+        .collect()
+}
+
+fn find_lib_name(toml: &str) -> Option<String> {
+    let mut section = "";
+    let mut package_name = None;
+    let mut lib_name = None;
+
+    for raw_line in toml.lines() {
+        let line = raw_line.trim();
+
+        if line.starts_with('[') && line.ends_with(']') {
+            section = &line[1..line.len() - 1];
+            continue;
+        }
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some((key, value)) = line.split_once('=') &&
+            key.trim() == "name"
+        {
+            let value = toml_string_value(value);
+            if value.is_empty() {
+                continue;
+            }
+            match section {
+                "lib" => lib_name = Some(value.to_string()),
+                "package" => package_name = Some(value.replace('-', "_")),
+                _ => {}
+            }
+        }
+    }
+
+    lib_name.or(package_name)
 }
 
+/// Extract a string value from the right-hand side of a TOML `key = value`
+/// line, dropping any trailing `# comment`.
+///
+/// Quoted values end at the closing quote; bare values end at the first `#`.
+fn toml_string_value(raw: &str) -> &str {
+    let raw = raw.trim();
+    match raw.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').map_or(quoted, |(value, _)| value),
+        None => raw.split_once('#').map_or(raw, |(value, _)| value).trim(),
+    }
+}
+
+/// Extract the program address from a `[programs.*]` value.
+///
+/// The value is either the address itself or an inline table such as
+/// `{ address = "...", idl = "..." }`, whose other keys are ignored.
+fn toml_program_address(raw: &str) -> &str {
+    let Some(table) = raw.trim().strip_prefix('{') else {
+        return toml_string_value(raw);
+    };
+    let table = table.split_once('}').map_or(table, |(entries, _)| entries);
+    table
+        .split(',')
+        .filter_map(|entry| entry.split_once('='))
+        .find(|(key, _)| key.trim() == "address")
+        .map_or("", |(_, value)| toml_string_value(value))
+}
+
+/// Cluster whose program ID is used when none is requested explicitly.
+pub const DEFAULT_CLUSTER: &str = "localnet";
+
 /// Load the swap program ID from Anchor.toml.
 ///
-/// This function attempts to parse the program ID from the `programs.*`
-/// section in Anchor.toml.
+/// This reads the ID for [`DEFAULT_CLUSTER`]; see
+/// [`load_swap_program_id_for_cluster`].
 ///
 /// # Arguments
 ///
@@ -139,42 +526,308 @@ pub fn load_swap_program(repo_dir: &Path) -> Result<PathBuf, ProgramLoadError> {
 /// * `Ok(Pubkey)` - The program ID
 /// * `Err(ProgramLoadError)` - If the program ID cannot be found or parsed
 pub fn load_swap_program_id(repo_dir: &Path) -> Result<Pubkey, ProgramLoadError> {
+    load_swap_program_id_for_cluster(repo_dir, DEFAULT_CLUSTER)
+}
+
+/// Load the swap program ID for a specific cluster from Anchor.toml.
+///
+/// The program is the one named by [`resolve_program_name`], matched against
+/// Anchor.toml keys with `-` and `_` treated alike, so the ID and the loaded
+/// `.so` always belong to the same program of a multi-program workspace.
+///
+/// This function prefers the `[programs.<cluster>]` section, then falls back
+/// to `[programs.localnet]`, and finally to the first `programs.*` section
+/// that defines the program. If Anchor.toml has no entry at all, the
+/// `declare_id!` invocation in the program crate's sources is used instead.
+///
+/// When `target/deploy/<program_name>-keypair.json` exists, its public key is
+/// the ID the program is deployed under. It is used if no ID is configured,
+/// and a configured ID that differs from it is reported as
+/// [`ProgramLoadError::ProgramIdMismatch`].
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+/// * `cluster` - The cluster name, e.g. `localnet` or `devnet`
+///
+/// # Returns
+///
+/// * `Ok(Pubkey)` - The program ID
+/// * `Err(ProgramLoadError)` - If the program ID cannot be found or parsed
+pub fn load_swap_program_id_for_cluster(
+    repo_dir: &Path,
+    cluster: &str,
+) -> Result<Pubkey, ProgramLoadError> {
     if !repo_dir.exists() {
         return Err(ProgramLoadError::RepoNotFound(repo_dir.to_path_buf()));
     }
 
-    let anchor_path = repo_dir.join("Anchor.toml");
+    let root = workspace_root(repo_dir);
+    let anchor_path = root.join("Anchor.toml");
     if !anchor_path.exists() {
         return Err(ProgramLoadError::AnchorTomlNotFound(anchor_path));
     }
 
+    let program_name = resolve_program_name(&root)?;
     let content = std::fs::read_to_string(&anchor_path)?;
-    let program_id =
-        find_program_id(&content, "swap-program").ok_or(ProgramLoadError::ProgramIdNotFound)?;
+    let configured = find_program_id(&content, &program_name, cluster)
+        .or_else(|| find_declared_program_id(&root, &program_name));
+    let keypair = read_deploy_keypair_id(&root, &program_name)?;
+    crate::debug::log(format_args!(
+        "program id for {} on {}: configured {:?}, deploy keypair {:?}",
+        program_name, cluster, configured, keypair
+    ));
+
+    let Some(program_id) = configured else {
+        return keypair.ok_or(ProgramLoadError::ProgramIdNotFound);
+    };
+    let configured = Pubkey::from_str(&program_id)
+        .map_err(|_| ProgramLoadError::InvalidProgramId(program_id))?;
+
+    match keypair {
+        Some(keypair) if keypair != configured => {
+            Err(ProgramLoadError::ProgramIdMismatch { configured, keypair })
+        }
+        _ => Ok(configured),
+    }
+}
+
+/// An account of an instruction as declared in the Anchor IDL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdlAccount {
+    pub name: String,
+    pub signer: bool,
+    pub writable: bool,
+}
+
+/// Read the accounts of an instruction from the program's Anchor IDL.
+///
+/// The IDL is read from `target/idl/<program_name>.json` under the workspace
+/// root. Both the current (`writable`/`signer`) and the pre-0.30
+/// (`isMut`/`isSigner`) flag names are understood.
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+/// * `instruction` - The instruction name, e.g. `make_offer`
+///
+/// # Returns
+///
+/// * `Some(Vec<IdlAccount>)` - The instruction's accounts in declared order
+/// * `None` - If there is no IDL, it cannot be parsed, or it lacks the instruction
+pub fn load_idl_instruction_accounts(
+    repo_dir: &Path,
+    instruction: &str,
+) -> Option<Vec<IdlAccount>> {
+    let entry = load_idl_instruction(repo_dir, instruction)?;
+    let flag = |account: &serde_json::Value, keys: [&str; 2]| {
+        keys.iter().any(|key| account.get(key).and_then(serde_json::Value::as_bool) == Some(true))
+    };
+    entry
+        .get("accounts")?
+        .as_array()?
+        .iter()
+        .map(|account| {
+            Some(IdlAccount {
+                name: account.get("name")?.as_str()?.to_string(),
+                signer: flag(account, ["signer", "isSigner"]),
+                writable: flag(account, ["writable", "isMut"]),
+            })
+        })
+        .collect()
+}
+
+/// Read the discriminator of an instruction from the program's Anchor IDL.
+///
+/// Only IDLs generated by Anchor 0.30 or later record discriminators; they
+/// also cover programs that declare a custom one instead of the hashed
+/// default.
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+/// * `instruction` - The instruction name, e.g. `make_offer`
+///
+/// # Returns
+///
+/// * `Some(Vec<u8>)` - The discriminator bytes the program dispatches on
+/// * `None` - If there is no IDL, or it records no discriminator for the instruction
+pub fn load_idl_instruction_discriminator(repo_dir: &Path, instruction: &str) -> Option<Vec<u8>> {
+    idl_discriminator(&load_idl_instruction(repo_dir, instruction)?)
+}
+
+/// An instruction as declared in the Anchor IDL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdlInstruction {
+    pub name: String,
+    /// Only recorded by IDLs generated by Anchor 0.30 or later.
+    pub discriminator: Option<Vec<u8>>,
+}
+
+/// Read every instruction declared in the program's Anchor IDL.
+///
+/// # Arguments
+///
+/// * `repo_dir` - Path to the user's repository directory
+///
+/// # Returns
+///
+/// * `Some(Vec<IdlInstruction>)` - The instructions in declared order
+/// * `None` - If there is no IDL or it cannot be parsed
+pub fn load_idl_instructions(repo_dir: &Path) -> Option<Vec<IdlInstruction>> {
+    load_idl(repo_dir)?
+        .get("instructions")?
+        .as_array()?
+        .iter()
+        .map(|entry| {
+            Some(IdlInstruction {
+                name: entry.get("name")?.as_str()?.to_string(),
+                discriminator: idl_discriminator(entry),
+            })
+        })
+        .collect()
+}
 
-    Pubkey::from_str(&program_id).map_err(|_| ProgramLoadError::InvalidProgramId(program_id))
+/// Read the discriminator bytes recorded on an IDL entry.
+fn idl_discriminator(entry: &serde_json::Value) -> Option<Vec<u8>> {
+    entry
+        .get("discriminator")?
+        .as_array()?
+        .iter()
+        .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+        .collect()
 }
 
-fn find_program_id(toml: &str, program_name: &str) -> Option<String> {
-    let mut in_programs_section = false;
+/// Read the program's Anchor IDL from `target/idl/<program_name>.json`.
+fn load_idl(repo_dir: &Path) -> Option<serde_json::Value> {
+    let root = workspace_root(repo_dir);
+    let path = root.join("target/idl").join(format!("{}.json", resolve_program_name(&root).ok()?));
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// Find an instruction's entry in the program's Anchor IDL.
+fn load_idl_instruction(repo_dir: &Path, instruction: &str) -> Option<serde_json::Value> {
+    let idl = load_idl(repo_dir)?;
+
+    // Pre-0.30 IDLs use camelCase instruction names
+    let camel_case = instruction
+        .split('_')
+        .enumerate()
+        .map(|(index, word)| {
+            let mut chars = word.chars();
+            match (index, chars.next()) {
+                (0, _) | (_, None) => word.to_string(),
+                (_, Some(first)) => first.to_uppercase().chain(chars).collect(),
+            }
+        })
+        .collect::<String>();
+    idl.get("instructions")?
+        .as_array()?
+        .iter()
+        .find(|entry| {
+            entry
+                .get("name")
+                .and_then(serde_json::Value::as_str)
+                .is_some_and(|name| name == instruction || name == camel_case)
+        })
+        .cloned()
+}
+
+/// Read the program ID from Anchor's deploy keypair, if one was generated.
+///
+/// The keypair file is a JSON array of 64 bytes whose last 32 bytes are the
+/// public key.
+fn read_deploy_keypair_id(
+    root: &Path,
+    program_name: &str,
+) -> Result<Option<Pubkey>, ProgramLoadError> {
+    let path = root.join("target/deploy").join(format!("{}-keypair.json", program_name));
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    let invalid = || ProgramLoadError::InvalidProgramId(path.display().to_string());
+    let bytes: Vec<u8> = serde_json::from_str(&content).map_err(|_| invalid())?;
+    let public_key: [u8; 32] = match bytes.len() {
+        64 => bytes[32..].try_into().map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+
+    Ok(Some(Pubkey::new_from_array(public_key)))
+}
+
+fn find_program_id(toml: &str, program_name: &str, cluster: &str) -> Option<String> {
+    let mut section = "";
+    let mut candidates: Vec<(&str, String)> = Vec::new();
 
     for raw_line in toml.lines() {
         let line = raw_line.trim();
 
         if line.starts_with('[') && line.ends_with(']') {
-            let section = &line[1..line.len() - 1];
-            in_programs_section = section == "programs" || section.starts_with("programs.");
+            section = &line[1..line.len() - 1];
             continue;
         }
 
+        let in_programs_section = section == "programs" || section.starts_with("programs.");
         if !in_programs_section || line.is_empty() || line.starts_with('#') {
             continue;
         }
 
         if let Some((key, value)) = line.split_once('=') &&
-            key.trim() == program_name
+            key.trim().replace('-', "_") == program_name.replace('-', "_")
         {
-            let value = value.trim().trim_matches('"');
+            let value = toml_program_address(value);
+            if !value.is_empty() {
+                candidates.push((section, value.to_string()));
+            }
+        }
+    }
+
+    let cluster_id = |name: &str| {
+        candidates
+            .iter()
+            .find(|(section, _)| section.strip_prefix("programs.") == Some(name))
+            .map(|(_, value)| value.clone())
+    };
+
+    cluster_id(cluster)
+        .or_else(|| cluster_id(DEFAULT_CLUSTER))
+        .or_else(|| candidates.first().map(|(_, value)| value.clone()))
+}
+
+/// Search the program sources for a `declare_id!("...")` invocation.
+///
+/// Only the crate named `program_name` is searched, `src/lib.rs` first and
+/// then every other file under `src/`, so a dependency's ID is never picked
+/// up in place of the swap program's.
+fn find_declared_program_id(root: &Path, program_name: &str) -> Option<String> {
+    let (program_dir, _) =
+        program_crates(root).into_iter().find(|(_, name)| name == program_name)?;
+    let src_dir = program_dir.join("src");
+
+    let mut sources = Vec::new();
+    collect_files_with_extension(&src_dir, "rs", &mut sources);
+    let lib_rs = src_dir.join("lib.rs");
+    sources.sort_by(|a, b| (*b == lib_rs).cmp(&(*a == lib_rs)).then_with(|| a.cmp(b)));
+
+    sources
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .find_map(|content| find_declare_id(&content))
+}
+
+fn find_declare_id(source: &str) -> Option<String> {
+    for raw_line in source.lines() {
+        let line = raw_line.trim();
+        if line.starts_with("//") {
+            continue;
+        }
+
+        if let Some((_, rest)) = line.split_once("declare_id!(") &&
+            let Some((literal, _)) = rest.split_once(')')
+        {
+            let value = literal.trim().trim_matches('"');
             if !value.is_empty() {
                 return Some(value.to_string());
             }
@@ -184,8 +837,13 @@ fn find_program_id(toml: &str, program_name: &str) -> Option<String> {
     None
 }
 
-/// Search for any .so file in the target directory.
-fn find_so_file_in_target(repo_dir: &Path) -> Option<PathBuf> {
+/// Search for a .so file in the target directory.
+///
+/// All candidates are collected and the choice is deterministic: files named
+/// `<program_name>.so` win, then the most recently modified, then the
+/// lexically smallest path. A newer skipped candidate is reported like in
+/// [`find_program_so`], so a stale binary can be diagnosed.
+fn find_so_file_in_target(repo_dir: &Path, program_name: &str) -> Option<PathBuf> {
     let target_dir = repo_dir.join("target");
     if !target_dir.exists() {
         return None;
@@ -193,44 +851,45 @@ fn find_so_file_in_target(repo_dir: &Path) -> Option<PathBuf> {
 
     // Search recursively for .so files
     let mut found = Vec::new();
-    if let Ok(entries) = std::fs::read_dir(&target_dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_dir() {
-                if let Some(found_in_subdir) = find_so_file_recursive(&path) {
-                    found.push(found_in_subdir);
-                }
-            } else if path.extension().is_some_and(|ext| ext == "so") {
-                found.push(path);
-            }
-        }
-    }
+    collect_files_with_extension(&target_dir, "so", &mut found);
 
-    // Return the first found .so file
-    found.into_iter().next()
+    let file_name = format!("{}.so", program_name);
+    let is_named = |path: &Path| path.file_name() == Some(OsStr::new(&file_name));
+    found.sort_by(|a, b| {
+        is_named(b)
+            .cmp(&is_named(a))
+            .then_with(|| modified_time(b).cmp(&modified_time(a)))
+            .then_with(|| a.cmp(b))
+    });
+
+    let selected = found.first()?.clone();
+    warn_if_stale(&selected, &found);
+    Some(selected)
 }
 
-/// Recursively search for .so files in a directory.
-fn find_so_file_recursive(dir: &Path) -> Option<PathBuf> {
+/// Recursively collect the files with a given extension in a directory.
+fn collect_files_with_extension(dir: &Path, extension: &str, found: &mut Vec<PathBuf>) {
     if let Ok(entries) = std::fs::read_dir(dir) {
         for entry in entries.flatten() {
             let path = entry.path();
             if path.is_dir() {
-                if let Some(found) = find_so_file_recursive(&path) {
-                    return Some(found);
-                }
-            } else if path.extension().is_some_and(|ext| ext == "so") {
-                return Some(path);
+                collect_files_with_extension(&path, extension, found);
+            } else if path.extension().is_some_and(|ext| ext == extension) {
+                found.push(path);
             }
         }
     }
-    None
+}
+
+/// Get the modification time of a file, if available.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
 }
 
 /// Load the program ELF bytes from a file path.
 ///
-/// This is a wrapper around Mollusk's file loading functionality that
-/// provides better error messages.
+/// The file is read directly rather than through Mollusk's `SBF_OUT_DIR`
+/// lookup, so loading a program has no process-global side effect.
 ///
 /// # Arguments
 ///
@@ -240,8 +899,139 @@ fn find_so_file_recursive(dir: &Path) -> Option<PathBuf> {
 ///
 /// * `Ok(Vec<u8>)` - The program ELF bytes
 /// * `Err(ProgramLoadError)` - If the file cannot be read
-#[allow(dead_code)]
 pub fn load_program_elf(path: &Path) -> Result<Vec<u8>, ProgramLoadError> {
-    let elf = file::load_program_elf(path.to_str().unwrap());
-    Ok(elf)
+    Ok(std::fs::read(path)?)
+}
+
+/// ELF section type of a note section.
+const SHT_NOTE: u32 = 7;
+/// Note type of a GNU build ID.
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Read the build ID embedded in a program's ELF notes.
+///
+/// Toolchains that pass `--build-id` to the linker emit a `.note.gnu.build-id`
+/// section; its value identifies the exact binary that was loaded, which helps
+/// tell a stale artifact apart from a genuine failure.
+///
+/// # Arguments
+///
+/// * `path` - Path to the program SO file
+///
+/// # Returns
+///
+/// * `Some(String)` - The build ID as lowercase hex
+/// * `None` - If the file cannot be read, is not a 64-bit ELF, or has no build ID
+pub fn program_build_id(path: &Path) -> Option<String> {
+    let elf = std::fs::read(path).ok()?;
+    if elf.get(0..4)? != b"\x7fELF" || *elf.get(4)? != 2 || *elf.get(5)? != 1 {
+        return None;
+    }
+
+    let bytes_at = |offset: usize, len: usize| elf.get(offset..offset.checked_add(len)?);
+    let u16_at = |offset: usize| bytes_at(offset, 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_at =
+        |offset: usize| bytes_at(offset, 4).and_then(|b| b.try_into().ok()).map(u32::from_le_bytes);
+    let u64_at =
+        |offset: usize| bytes_at(offset, 8).and_then(|b| b.try_into().ok()).map(u64::from_le_bytes);
+
+    let section_headers = usize::try_from(u64_at(0x28)?).ok()?;
+    let header_size = usize::from(u16_at(0x3a)?);
+    let section_count = usize::from(u16_at(0x3c)?);
+
+    for index in 0..section_count {
+        let header = section_headers.checked_add(index.checked_mul(header_size)?)?;
+        if u32_at(header.checked_add(4)?)? != SHT_NOTE {
+            continue;
+        }
+        let start = usize::try_from(u64_at(header.checked_add(0x18)?)?).ok()?;
+        let size = usize::try_from(u64_at(header.checked_add(0x20)?)?).ok()?;
+        let notes = elf.get(start..start.checked_add(size)?)?;
+        if let Some(build_id) = find_build_id_note(notes) {
+            return Some(crate::mollusk::hex(build_id));
+        }
+    }
+
+    None
+}
+
+/// Find the GNU build ID among the notes of a note section.
+fn find_build_id_note(mut notes: &[u8]) -> Option<&[u8]> {
+    let align = |len: usize| len.div_ceil(4).checked_mul(4);
+    while notes.len() >= 12 {
+        let name_size = usize::try_from(u32::from_le_bytes(notes[0..4].try_into().ok()?)).ok()?;
+        let desc_size = usize::try_from(u32::from_le_bytes(notes[4..8].try_into().ok()?)).ok()?;
+        let note_type = u32::from_le_bytes(notes[8..12].try_into().ok()?);
+        let desc_start = align(name_size)?.checked_add(12)?;
+        let name = notes.get(12..name_size.checked_add(12)?)?;
+        let desc = notes.get(desc_start..desc_start.checked_add(desc_size)?)?;
+
+        if note_type == NT_GNU_BUILD_ID && name == b"GNU\0" {
+            return Some(desc);
+        }
+        notes = notes.get(desc_start.checked_add(align(desc_size)?)?..)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_string_value_drops_trailing_comment() {
+        assert_eq!(toml_string_value(r#" "swap_program" # the swap"#), "swap_program");
+        assert_eq!(toml_string_value(" swap_program # the swap"), "swap_program");
+    }
+
+    #[test]
+    fn toml_string_value_trims_leading_whitespace() {
+        assert_eq!(toml_string_value("   \"swap_program\""), "swap_program");
+        assert_eq!(toml_string_value("\t swap_program"), "swap_program");
+    }
+
+    #[test]
+    fn find_lib_name_reads_tab_indented_keys() {
+        let toml = "[package]\n\tname = \"swap-program\" # package\n";
+        assert_eq!(find_lib_name(toml).as_deref(), Some("swap_program"));
+
+        let toml = "[package]\nname = \"swap-program\"\n\n[lib]\n\tname\t= \"swap\"\n";
+        assert_eq!(find_lib_name(toml).as_deref(), Some("swap"));
+    }
+
+    #[test]
+    fn toml_program_address_reads_inline_table() {
+        let address = "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS";
+        let toml = format!(
+            "[programs.localnet]\nswap = {{ address = \"{}\", idl = \"target/idl/swap.json\" }}\n",
+            address
+        );
+
+        let program_id = find_program_id(&toml, "swap", DEFAULT_CLUSTER);
+        assert_eq!(program_id.as_deref(), Some(address));
+        assert!(program_id.as_deref().and_then(|id| Pubkey::from_str(id).ok()).is_some());
+        assert_eq!(
+            toml_program_address(r#"{ idl = "target/idl/swap.json", address = "abc" }"#),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn program_build_id_rejects_out_of_range_section_headers() {
+        let mut elf = vec![0u8; 0x40];
+        elf[0..4].copy_from_slice(b"\x7fELF");
+        elf[4] = 2;
+        elf[5] = 1;
+        elf[0x28..0x30].copy_from_slice(&(u64::MAX - 2).to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&0x40u16.to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&1u16.to_le_bytes());
+
+        let path =
+            std::env::temp_dir().join(format!("swap-tester-build-id-{}.so", std::process::id()));
+        std::fs::write(&path, &elf).unwrap();
+        let build_id = program_build_id(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(build_id, None);
+    }
 }
@@ -14,20 +14,36 @@
 
 //! Test context module for managing state during testing.
 
-use mollusk_svm::{
-    Mollusk,
-    result::{Check, InstructionResult},
+use crate::mollusk::{
+    extensions::{ACCOUNT_TYPE_ACCOUNT, with_extensions},
+    hex,
+    program_loader::IdlInstruction,
 };
+use mollusk_svm::{Mollusk, result::InstructionResult, sysvar::Sysvars};
+use mollusk_svm_programs_token::{token, token2022};
 use solana_account::Account;
 use solana_instruction::Instruction;
 use solana_instruction_error::InstructionError;
+use solana_program_option::COption;
 use solana_pubkey::Pubkey;
+use solana_rent::Rent;
+use spl_token_interface::state::{Account as TokenAccount, AccountState};
 use std::collections::HashMap;
 
+/// Anchor's `InstructionFallbackNotFound` error code, returned when no handler
+/// matches the instruction discriminator.
+const ANCHOR_INSTRUCTION_FALLBACK_NOT_FOUND: u32 = 101;
+
+/// Target slot duration used to advance the slot along with the clock.
+const MS_PER_SLOT: u64 = 400;
+
 /// Error type for test context operations.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TestContextError {
+    /// The program failed cleanly by returning an error.
     ExecutionError(String),
+    /// The program panicked, aborted or faulted instead of returning an error.
+    ProgramAborted(String),
     ValidationError(String),
     AccountNotFound(String),
 }
@@ -38,6 +54,11 @@ impl std::fmt::Display for TestContextError {
             TestContextError::ExecutionError(msg) => {
                 write!(f, "Instruction execution failed: {}", msg)
             }
+            TestContextError::ProgramAborted(msg) => write!(
+                f,
+                "Program aborted instead of returning an error (a panic or unwrap?): {}",
+                msg
+            ),
             TestContextError::ValidationError(msg) => write!(f, "Validation failed: {}", msg),
             TestContextError::AccountNotFound(msg) => write!(f, "Account not found: {}", msg),
         }
@@ -46,6 +67,50 @@ impl std::fmt::Display for TestContextError {
 
 impl std::error::Error for TestContextError {}
 
+impl TestContextError {
+    /// Validation error for a value that differs from what a check expected.
+    pub fn mismatch(
+        field: &str,
+        expected: impl std::fmt::Display,
+        actual: impl std::fmt::Display,
+    ) -> Self {
+        TestContextError::ValidationError(format!(
+            "{} mismatch: expected {}, got {}",
+            field, expected, actual
+        ))
+    }
+
+    /// Validation error for an instruction that succeeded but should have been
+    /// rejected.
+    pub fn expected_failure(msg: impl Into<String>) -> Self {
+        TestContextError::ValidationError(format!("expected failure: {}", msg.into()))
+    }
+
+    /// Validation error for an instruction that was rejected by aborting
+    /// rather than by returning an error.
+    pub fn aborted_instead_of_error(msg: impl Into<String>) -> Self {
+        TestContextError::ValidationError(format!(
+            "expected an error return, but the program aborted; return an error (e.g. with \
+             `require!` or `?`) instead of unwrapping or panicking: {}",
+            msg.into()
+        ))
+    }
+
+    /// Validation error for account data too short to decode, showing its
+    /// length and leading bytes so an empty, uninitialized or foreign account
+    /// can be told apart.
+    pub fn data_too_short(what: &str, data: &[u8], expected_len: usize) -> Self {
+        let preview = data_preview(data);
+        TestContextError::ValidationError(format!(
+            "{} data too short: {} bytes, expected at least {} ({})",
+            what,
+            data.len(),
+            expected_len,
+            preview
+        ))
+    }
+}
+
 impl From<InstructionError> for TestContextError {
     fn from(err: InstructionError) -> Self {
         TestContextError::ExecutionError(format!("{:?}", err))
@@ -58,6 +123,140 @@ impl From<crate::mollusk::ProgramLoadError> for TestContextError {
     }
 }
 
+/// Show the first bytes of account data as hex, or `empty`.
+fn data_preview(data: &[u8]) -> String {
+    const PREVIEW_LEN: usize = 16;
+    match data.len() {
+        0 => "empty".to_string(),
+        len => {
+            let shown = hex(&data[..len.min(PREVIEW_LEN)]);
+            if len > PREVIEW_LEN { format!("{}...", shown) } else { shown }
+        }
+    }
+}
+
+/// An expectation about the outcome of an instruction, checked by
+/// [`SwapTestContext::execute_and_validate`].
+///
+/// A failing check describes itself, naming its kind, the account and the
+/// expected and actual values, e.g. `lamports check on <pubkey>: expected 10,
+/// got 5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultCheck<'a> {
+    /// The instruction succeeded.
+    Success,
+    /// The account holds exactly this many lamports.
+    Lamports(&'a Pubkey, u64),
+    /// The account holds exactly this data.
+    Data(&'a Pubkey, &'a [u8]),
+    /// The account is owned by this program.
+    Owner(&'a Pubkey, &'a Pubkey),
+    /// The account is or is not executable.
+    Executable(&'a Pubkey, bool),
+}
+
+impl ResultCheck<'_> {
+    /// Compare the check against an instruction result.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the result meets the expectation
+    /// * `Err(String)` - A description of the mismatch
+    fn verify(&self, result: &InstructionResult) -> Result<(), String> {
+        let account = |kind: &str, pubkey: &Pubkey| {
+            result
+                .resulting_accounts
+                .iter()
+                .find(|(key, _)| key == pubkey)
+                .map(|(_, account)| account)
+                .ok_or_else(|| {
+                    format!("{} check on {}: account missing from the result", kind, pubkey)
+                })
+        };
+        let compare = |kind: &str, pubkey: &Pubkey, expected: String, actual: String| {
+            if expected == actual {
+                Ok(())
+            } else {
+                Err(format!("{} check on {}: expected {}, got {}", kind, pubkey, expected, actual))
+            }
+        };
+
+        match *self {
+            ResultCheck::Success if result.program_result.is_err() => {
+                Err(format!("success check: expected success, got {:?}", result.program_result))
+            }
+            ResultCheck::Success => Ok(()),
+            ResultCheck::Lamports(pubkey, expected) => {
+                let actual = account("lamports", pubkey)?.lamports;
+                compare("lamports", pubkey, expected.to_string(), actual.to_string())
+            }
+            ResultCheck::Data(pubkey, expected) => {
+                let actual = &account("data", pubkey)?.data;
+                if actual.as_slice() == expected {
+                    return Ok(());
+                }
+                let first_difference = expected
+                    .iter()
+                    .zip(actual.iter())
+                    .position(|(a, b)| a != b)
+                    .unwrap_or_else(|| expected.len().min(actual.len()));
+                Err(format!(
+                    "data check on {}: expected {} bytes ({}), got {} bytes ({}), first \
+                     difference at byte {}",
+                    pubkey,
+                    expected.len(),
+                    data_preview(expected),
+                    actual.len(),
+                    data_preview(actual),
+                    first_difference
+                ))
+            }
+            ResultCheck::Owner(pubkey, expected) => {
+                let actual = account("owner", pubkey)?.owner;
+                compare("owner", pubkey, expected.to_string(), actual.to_string())
+            }
+            ResultCheck::Executable(pubkey, expected) => {
+                let actual = account("executable", pubkey)?.executable;
+                compare("executable", pubkey, expected.to_string(), actual.to_string())
+            }
+        }
+    }
+}
+
+/// A saved copy of the account state of a [`SwapTestContext`].
+#[derive(Debug, Clone)]
+pub struct AccountSnapshot {
+    accounts: HashMap<Pubkey, Account>,
+}
+
+/// How an account changed between a snapshot and the current state.
+///
+/// Each field holds the `(before, after)` values. A missing account is
+/// reported as an empty default account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub pubkey: Pubkey,
+    pub lamports: (u64, u64),
+    pub owner: (Pubkey, Pubkey),
+    pub data_len: (usize, usize),
+}
+
+impl std::fmt::Display for AccountDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.pubkey)?;
+        if self.lamports.0 != self.lamports.1 {
+            write!(f, " lamports {} -> {}", self.lamports.0, self.lamports.1)?;
+        }
+        if self.owner.0 != self.owner.1 {
+            write!(f, " owner {} -> {}", self.owner.0, self.owner.1)?;
+        }
+        if self.data_len.0 != self.data_len.1 {
+            write!(f, " data length {} -> {}", self.data_len.0, self.data_len.1)?;
+        }
+        Ok(())
+    }
+}
+
 /// A test context for the swap program.
 ///
 /// This struct manages the state of accounts during testing and provides
@@ -69,6 +268,12 @@ pub struct SwapTestContext {
     accounts: HashMap<Pubkey, Account>,
     /// The program ID being tested.
     program_id: Pubkey,
+    /// The return data set by the last successful instruction.
+    last_return_data: Option<Vec<u8>>,
+    /// The build ID of the loaded program binary, if it embeds one.
+    program_build_id: Option<String>,
+    /// The instructions declared in the program's IDL, if one was found.
+    idl_instructions: Vec<IdlInstruction>,
 }
 
 impl SwapTestContext {
@@ -82,8 +287,17 @@ impl SwapTestContext {
     /// # Returns
     ///
     /// * `Ok(SwapTestContext)` - A new test context
-    pub fn new(mollusk: Mollusk, program_id: Pubkey) -> Result<Self, TestContextError> {
-        Ok(Self { mollusk, accounts: HashMap::new(), program_id })
+    pub fn new(mut mollusk: Mollusk, program_id: Pubkey) -> Result<Self, TestContextError> {
+        // Collect program logs so failures can report them
+        mollusk.logger.get_or_insert_with(Default::default);
+        Ok(Self {
+            mollusk,
+            accounts: HashMap::new(),
+            program_id,
+            last_return_data: None,
+            program_build_id: None,
+            idl_instructions: Vec::new(),
+        })
     }
 
     /// Get the program ID.
@@ -91,6 +305,54 @@ impl SwapTestContext {
         self.program_id
     }
 
+    /// Get the build ID of the loaded program binary, which identifies the
+    /// exact artifact under test.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(String)` - The build ID as lowercase hex
+    /// * `None` - If the binary embeds no build ID, or it was not loaded from a file
+    #[allow(dead_code)]
+    pub fn program_build_id(&self) -> Option<&str> {
+        self.program_build_id.as_deref()
+    }
+
+    /// Record the build ID of the loaded program binary.
+    ///
+    /// # Arguments
+    ///
+    /// * `build_id` - The build ID read with [`crate::mollusk::program_build_id`]
+    pub fn set_program_build_id(&mut self, build_id: Option<String>) {
+        self.program_build_id = build_id;
+    }
+
+    /// Record the instructions declared in the program's IDL, so an
+    /// unknown-instruction failure can name the instructions the program
+    /// actually handles.
+    ///
+    /// # Arguments
+    ///
+    /// * `instructions` - The instructions read with [`crate::mollusk::load_idl_instructions`]
+    pub fn set_idl_instructions(&mut self, instructions: Vec<IdlInstruction>) {
+        self.idl_instructions = instructions;
+    }
+
+    /// Get the underlying Mollusk instance.
+    #[allow(dead_code)]
+    pub fn mollusk(&self) -> &Mollusk {
+        &self.mollusk
+    }
+
+    /// Get mutable access to the underlying Mollusk instance, e.g. to register
+    /// an extra program before running swap instructions.
+    ///
+    /// Instructions processed directly through Mollusk bypass the context's
+    /// account bookkeeping: their resulting accounts are not committed, and
+    /// program logs are not cleared beforehand.
+    pub fn mollusk_mut(&mut self) -> &mut Mollusk {
+        &mut self.mollusk
+    }
+
     /// Add an account to the test context.
     ///
     /// # Arguments
@@ -101,6 +363,36 @@ impl SwapTestContext {
         self.accounts.insert(pubkey, account);
     }
 
+    /// Add an account funded with exactly the rent-exempt minimum for its
+    /// data under the context's rent.
+    ///
+    /// # Arguments
+    ///
+    /// * `pubkey` - The account's public key
+    /// * `account` - The account data; its lamports are replaced
+    pub fn add_rent_exempt_account(&mut self, pubkey: Pubkey, account: Account) {
+        let lamports = self.rent().minimum_balance(account.data.len());
+        self.add_account(pubkey, Account { lamports, ..account });
+    }
+
+    /// Remove an account from the test context.
+    ///
+    /// The account is no longer passed to Mollusk, so instructions see it as
+    /// missing rather than as an empty placeholder.
+    ///
+    /// # Arguments
+    ///
+    /// * `pubkey` - The account's public key
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Account)` - The removed account if it existed
+    /// * `None` - If the account did not exist
+    #[allow(dead_code)]
+    pub fn remove_account(&mut self, pubkey: &Pubkey) -> Option<Account> {
+        self.accounts.remove(pubkey)
+    }
+
     /// Get an account from the test context.
     ///
     /// # Arguments
@@ -115,8 +407,64 @@ impl SwapTestContext {
         self.accounts.get(pubkey).cloned()
     }
 
+    /// Check whether an account has been closed.
+    ///
+    /// Mollusk keeps closed accounts as zero-lamport entries, so an account
+    /// counts as closed when it is missing, or when it holds no lamports, no
+    /// data and is owned by the system program.
+    ///
+    /// # Arguments
+    ///
+    /// * `pubkey` - The account's public key
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the account is closed
+    pub fn is_closed(&self, pubkey: &Pubkey) -> bool {
+        self.accounts.get(pubkey).is_none_or(|account| {
+            account.lamports == 0 &&
+                account.data.is_empty() &&
+                account.owner == solana_system_program::id()
+        })
+    }
+
+    /// Check whether an account holds enough lamports to be rent exempt.
+    ///
+    /// # Arguments
+    ///
+    /// * `pubkey` - The account's public key
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the account exists and is rent exempt for its size
+    pub fn is_rent_exempt(&self, pubkey: &Pubkey) -> bool {
+        self.accounts.get(pubkey).is_some_and(|account| {
+            account.lamports >= self.rent().minimum_balance(account.data.len())
+        })
+    }
+
+    /// Get all accounts owned by a program.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owning program's public key
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(Pubkey, Account)>` - The matching accounts, in no particular order
+    pub fn accounts_owned_by(&self, owner: &Pubkey) -> Vec<(Pubkey, Account)> {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.owner == *owner)
+            .map(|(pubkey, account)| (*pubkey, account.clone()))
+            .collect()
+    }
+
     /// Execute an instruction and update the account state.
     ///
+    /// The account state is only updated when the instruction succeeds; on
+    /// failure it is left exactly as it was before the call.
+    ///
     /// # Arguments
     ///
     /// * `instruction` - The instruction to execute
@@ -129,24 +477,227 @@ impl SwapTestContext {
         &mut self,
         instruction: &Instruction,
     ) -> Result<(), TestContextError> {
+        self.execute_instruction_metered(instruction).map(|_| ())
+    }
+
+    /// Execute an instruction, update the account state and report its cost.
+    ///
+    /// Like [`SwapTestContext::execute_instruction`], a failed instruction
+    /// leaves the account state untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `instruction` - The instruction to execute
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The compute units consumed by the instruction
+    /// * `Err(TestContextError)` - If execution failed
+    pub fn execute_instruction_metered(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<u64, TestContextError> {
+        self.execute_instruction_full(instruction).map(|result| result.compute_units_consumed)
+    }
+
+    /// Execute an instruction, update the account state and return the full
+    /// Mollusk result.
+    ///
+    /// This gives advanced checks access to the return data and the resulting
+    /// accounts as reported by Mollusk. Like
+    /// [`SwapTestContext::execute_instruction`], a failed instruction leaves the
+    /// account state untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `instruction` - The instruction to execute
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(InstructionResult)` - The result of the successful instruction
+    /// * `Err(TestContextError)` - If execution failed
+    pub fn execute_instruction_full(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<InstructionResult, TestContextError> {
+        self.clear_program_logs();
         let result: InstructionResult =
             self.mollusk.process_instruction(instruction, &self.get_account_list());
+        crate::report::add_compute_units(result.compute_units_consumed);
+        trace_instruction(instruction, &result);
 
         // Check if execution was successful
         if result.program_result.is_err() {
-            return Err(TestContextError::ExecutionError(format!("{:?}", result.program_result)));
+            return Err(failure_error(&result, self.failure_message(instruction, &result)));
         }
 
-        // Update account state from the result
-        for (pubkey, account) in result.resulting_accounts {
-            self.accounts.insert(pubkey, account);
+        self.commit_accounts(result.resulting_accounts.clone());
+        self.record_return_data(&result);
+
+        Ok(result)
+    }
+
+    /// Get the return data set by the last successful instruction.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Vec<u8>)` - The bytes passed to `set_return_data`
+    /// * `None` - If no instruction has succeeded yet, or the last one set no return data
+    #[allow(dead_code)]
+    pub fn last_return_data(&self) -> Option<Vec<u8>> {
+        self.last_return_data.clone()
+    }
+
+    /// Execute an instruction with some accounts substituted for this call
+    /// only.
+    ///
+    /// The overrides replace or add accounts in the list passed to Mollusk,
+    /// and the account state is left untouched whether the instruction
+    /// succeeds or fails, so a negative check needs no snapshot to restore.
+    /// Only the return data of a successful instruction is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `instruction` - The instruction to execute
+    /// * `overrides` - Accounts to use instead of, or in addition to, the context's
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the instruction executed successfully
+    /// * `Err(TestContextError)` - If execution failed
+    pub fn execute_instruction_with_overrides(
+        &mut self,
+        instruction: &Instruction,
+        overrides: &[(Pubkey, Account)],
+    ) -> Result<(), TestContextError> {
+        let mut accounts = self.accounts.clone();
+        accounts.extend(overrides.iter().cloned());
+        let account_list: Vec<(Pubkey, Account)> = accounts.into_iter().collect();
+
+        self.clear_program_logs();
+        let result: InstructionResult =
+            self.mollusk.process_instruction(instruction, &account_list);
+        crate::report::add_compute_units(result.compute_units_consumed);
+        trace_instruction(instruction, &result);
+
+        if result.program_result.is_err() {
+            return Err(failure_error(&result, self.failure_message(instruction, &result)));
+        }
+
+        self.record_return_data(&result);
+        Ok(())
+    }
+
+    /// Execute an instruction and require it to fail with a specific custom
+    /// program error.
+    ///
+    /// The account state is left untouched either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `instruction` - The instruction to execute
+    /// * `code` - The expected `InstructionError::Custom` code
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the instruction failed with `Custom(code)`
+    /// * `Err(TestContextError)` - If it succeeded or failed with another error
+    pub fn expect_custom_error(
+        &mut self,
+        instruction: &Instruction,
+        code: u32,
+    ) -> Result<(), TestContextError> {
+        self.clear_program_logs();
+        let result: InstructionResult =
+            self.mollusk.process_instruction(instruction, &self.get_account_list());
+        crate::report::add_compute_units(result.compute_units_consumed);
+        trace_instruction(instruction, &result);
+
+        match result.raw_result {
+            Ok(()) => {
+                self.record_return_data(&result);
+                Err(TestContextError::expected_failure(format!(
+                    "instruction succeeded instead of failing with Custom({})",
+                    code
+                )))
+            }
+            Err(InstructionError::Custom(actual)) if actual == code => Ok(()),
+            Err(InstructionError::ProgramFailedToComplete) => {
+                Err(TestContextError::ProgramAborted(format!(
+                    "expected Custom({}): {}",
+                    code,
+                    self.failure_message(instruction, &result)
+                )))
+            }
+            Err(_) => Err(TestContextError::mismatch(
+                "program error",
+                format!("Custom({})", code),
+                self.failure_message(instruction, &result),
+            )),
+        }
+    }
+
+    /// Execute several instructions atomically, like a transaction.
+    ///
+    /// The instructions run in order through Mollusk's instruction chain, each
+    /// seeing the accounts left by the one before. The resulting accounts are
+    /// only applied if every instruction succeeds, so a failure leaves the
+    /// context untouched. The compute units of the whole chain are reported,
+    /// and the return data is the last instruction's.
+    ///
+    /// # Arguments
+    ///
+    /// * `instructions` - The instructions to execute
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If all instructions executed successfully
+    /// * `Err(TestContextError)` - If any instruction failed
+    #[allow(dead_code)]
+    pub fn execute_transaction(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<(), TestContextError> {
+        self.clear_program_logs();
+        let result: InstructionResult =
+            self.mollusk.process_instruction_chain(instructions, &self.get_account_list());
+        crate::report::add_compute_units(result.compute_units_consumed);
+        crate::debug::log(format_args!(
+            "executed a chain of {} instructions: {:?}, {} compute units",
+            instructions.len(),
+            result.program_result,
+            result.compute_units_consumed
+        ));
+
+        if result.program_result.is_err() {
+            // The chain stops at the failing instruction without saying which
+            // one it was, so no single discriminator can be diagnosed
+            return Err(failure_error(
+                &result,
+                format!(
+                    "transaction of {} instructions: {}",
+                    instructions.len(),
+                    execution_failure(None, &result, &self.program_logs(), &self.idl_instructions)
+                ),
+            ));
         }
 
+        self.commit_accounts(result.resulting_accounts);
+        self.record_return_data(&result);
+
         Ok(())
     }
 
     /// Execute an instruction and validate the result.
     ///
+    /// The account state is only updated when the instruction succeeds and all
+    /// checks pass; on failure it is left exactly as it was before the call.
+    ///
+    /// The instruction runs once and the checks are compared against its
+    /// result in order. The first failing check is returned as a
+    /// `ValidationError` naming the check, the account and the expected and
+    /// actual values.
+    ///
     /// # Arguments
     ///
     /// * `instruction` - The instruction to execute
@@ -160,27 +711,219 @@ impl SwapTestContext {
     pub fn execute_and_validate(
         &mut self,
         instruction: &Instruction,
-        checks: &[Check],
+        checks: &[ResultCheck],
     ) -> Result<(), TestContextError> {
-        let result: InstructionResult = self.mollusk.process_and_validate_instruction(
-            instruction,
-            &self.get_account_list(),
-            checks,
-        );
+        self.clear_program_logs();
+        let result: InstructionResult =
+            self.mollusk.process_instruction(instruction, &self.get_account_list());
+        crate::report::add_compute_units(result.compute_units_consumed);
+        trace_instruction(instruction, &result);
 
         // Check if execution was successful
         if result.program_result.is_err() {
-            return Err(TestContextError::ExecutionError(format!("{:?}", result.program_result)));
+            return Err(failure_error(&result, self.failure_message(instruction, &result)));
         }
 
-        // Update account state from the result
-        for (pubkey, account) in result.resulting_accounts {
-            self.accounts.insert(pubkey, account);
+        for check in checks {
+            check.verify(&result).map_err(TestContextError::ValidationError)?;
         }
 
+        self.record_return_data(&result);
+        self.commit_accounts(result.resulting_accounts);
+
         Ok(())
     }
 
+    /// Take a snapshot of the current account state.
+    ///
+    /// # Returns
+    ///
+    /// * `AccountSnapshot` - A copy of all accounts in the context
+    pub fn snapshot(&self) -> AccountSnapshot {
+        AccountSnapshot { accounts: self.accounts.clone() }
+    }
+
+    /// Restore the account state from a snapshot.
+    ///
+    /// Accounts added after the snapshot was taken are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The snapshot to restore
+    pub fn restore(&mut self, snapshot: AccountSnapshot) {
+        self.accounts = snapshot.accounts;
+    }
+
+    /// Report the accounts whose lamports, owner or data length changed since
+    /// a snapshot was taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The earlier state to compare against
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<AccountDiff>` - The changed accounts, ordered by public key
+    #[allow(dead_code)]
+    pub fn diff_since(&self, snapshot: &AccountSnapshot) -> Vec<AccountDiff> {
+        let mut pubkeys: Vec<&Pubkey> =
+            snapshot.accounts.keys().chain(self.accounts.keys()).collect();
+        pubkeys.sort();
+        pubkeys.dedup();
+
+        let empty = Account::default();
+        pubkeys
+            .into_iter()
+            .filter_map(|pubkey| {
+                let before = snapshot.accounts.get(pubkey).unwrap_or(&empty);
+                let after = self.accounts.get(pubkey).unwrap_or(&empty);
+                let diff = AccountDiff {
+                    pubkey: *pubkey,
+                    lamports: (before.lamports, after.lamports),
+                    owner: (before.owner, after.owner),
+                    data_len: (before.data.len(), after.data.len()),
+                };
+                (diff.lamports.0 != diff.lamports.1 ||
+                    diff.owner.0 != diff.owner.1 ||
+                    diff.data_len.0 != diff.data_len.1)
+                    .then_some(diff)
+            })
+            .collect()
+    }
+
+    /// Require an account's lamports to have changed by exactly
+    /// `expected_delta` since a snapshot was taken.
+    ///
+    /// A missing account counts as holding no lamports.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The earlier state to compare against
+    /// * `pubkey` - The account's public key
+    /// * `expected_delta` - The expected signed change in lamports
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the change matches
+    /// * `Err(TestContextError)` - A mismatch naming the account and both deltas
+    pub fn assert_lamport_delta(
+        &self,
+        snapshot: &AccountSnapshot,
+        pubkey: &Pubkey,
+        expected_delta: i128,
+    ) -> Result<(), TestContextError> {
+        let lamports = |accounts: &HashMap<Pubkey, Account>| {
+            accounts.get(pubkey).map_or(0, |account| i128::from(account.lamports))
+        };
+        let delta = lamports(&self.accounts) - lamports(&snapshot.accounts);
+        if delta == expected_delta {
+            Ok(())
+        } else {
+            Err(TestContextError::mismatch(
+                &format!("lamport change of {}", pubkey),
+                expected_delta,
+                delta,
+            ))
+        }
+    }
+
+    /// Set the compute unit limit for each instruction.
+    ///
+    /// Mollusk's default budget is used until this is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `compute_unit_limit` - The maximum compute units an instruction may consume
+    #[allow(dead_code)]
+    pub fn set_compute_unit_limit(&mut self, compute_unit_limit: u64) {
+        self.mollusk.compute_budget.compute_unit_limit = compute_unit_limit;
+    }
+
+    /// Get the rent sysvar seen by the program.
+    pub fn rent(&self) -> Rent {
+        self.mollusk.sysvars.rent
+    }
+
+    /// Set the rent sysvar seen by the program.
+    ///
+    /// Accounts created afterwards through the context are funded against
+    /// this rent, and [`SwapTestContext::is_rent_exempt`] checks against it.
+    ///
+    /// # Arguments
+    ///
+    /// * `rent` - The rent parameters to use
+    #[allow(dead_code)]
+    pub fn set_rent(&mut self, rent: Rent) {
+        self.mollusk.sysvars.rent = rent;
+    }
+
+    /// Set the clock sysvar seen by the program.
+    ///
+    /// # Arguments
+    ///
+    /// * `unix_timestamp` - The clock's unix timestamp
+    /// * `slot` - The clock's slot
+    #[allow(dead_code)]
+    pub fn set_clock(&mut self, unix_timestamp: i64, slot: u64) {
+        let clock = &mut self.mollusk.sysvars.clock;
+        clock.unix_timestamp = unix_timestamp;
+        clock.slot = slot;
+    }
+
+    /// Advance the clock sysvar by a number of seconds.
+    ///
+    /// The slot is advanced by the number of 400ms slots that fit in the
+    /// interval, so slot-based and time-based logic stay consistent. The clock
+    /// only moves forward, and both values saturate instead of overflowing;
+    /// use [`SwapTestContext::set_clock`] to move it back.
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds` - The number of seconds to advance the clock by
+    #[allow(dead_code)]
+    pub fn warp_forward(&mut self, seconds: u64) {
+        let clock = &self.mollusk.sysvars.clock;
+        let slots = seconds.saturating_mul(1000) / MS_PER_SLOT;
+        let unix_timestamp =
+            clock.unix_timestamp.saturating_add(i64::try_from(seconds).unwrap_or(i64::MAX));
+        self.set_clock(unix_timestamp, clock.slot.saturating_add(slots));
+    }
+
+    /// Reset the clock sysvar to its default value.
+    ///
+    /// Scenarios that manipulate the clock should call this before the
+    /// context is reused, so the warped time does not leak into later checks.
+    #[allow(dead_code)]
+    pub fn reset_clock(&mut self) {
+        self.mollusk.sysvars.clock = Sysvars::default().clock;
+    }
+
+    /// Apply the accounts produced by a successful instruction.
+    ///
+    /// This is the only place executed results reach the account state, and it
+    /// must not be called for failed instructions.
+    fn commit_accounts(&mut self, resulting_accounts: Vec<(Pubkey, Account)>) {
+        for (pubkey, account) in resulting_accounts {
+            self.accounts.insert(pubkey, account);
+        }
+    }
+
+    /// Get the log lines recorded by the most recent instruction.
+    pub fn program_logs(&self) -> Vec<String> {
+        self.mollusk
+            .logger
+            .as_ref()
+            .map(|logger| logger.borrow().get_recorded_content().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Discard the log lines recorded so far.
+    fn clear_program_logs(&mut self) {
+        if let Some(logger) = &self.mollusk.logger {
+            *logger.borrow_mut() = Default::default();
+        }
+    }
+
     /// Get the current account list for Mollusk.
     fn get_account_list(&self) -> Vec<(Pubkey, Account)> {
         self.accounts.iter().map(|(pubkey, account)| (*pubkey, account.clone())).collect()
@@ -199,16 +942,58 @@ impl SwapTestContext {
     ///
     /// * `Pubkey` - The public key of the new account
     pub fn create_funded_account(&mut self, lamports: u64) -> Pubkey {
+        self.create_funded_account_with_owner(lamports, solana_system_program::id(), 0)
+    }
+
+    /// Create a new keypair and add a funded account owned by `owner`.
+    ///
+    /// The account's data is zero-filled to `data_len` bytes, which lets a
+    /// check simulate a pre-existing program account such as an offer PDA.
+    ///
+    /// # Arguments
+    ///
+    /// * `lamports` - Initial lamports to fund the account with
+    /// * `owner` - The program owning the account
+    /// * `data_len` - Size of the account's data
+    ///
+    /// # Returns
+    ///
+    /// * `Pubkey` - The public key of the new account
+    pub fn create_funded_account_with_owner(
+        &mut self,
+        lamports: u64,
+        owner: Pubkey,
+        data_len: usize,
+    ) -> Pubkey {
         let pubkey = Pubkey::new_unique();
-        let account =
-            Account { lamports, owner: solana_system_program::id(), ..Default::default() };
+        let account = Account { lamports, data: vec![0; data_len], owner, ..Default::default() };
         self.add_account(pubkey, account);
         pubkey
     }
 
+    /// Add lamports to an account, creating it if it does not exist.
+    ///
+    /// An existing account keeps its data and owner; a missing one is added as
+    /// an empty system account.
+    ///
+    /// # Arguments
+    ///
+    /// * `pubkey` - The account to fund
+    /// * `lamports` - Lamports to add
+    #[allow(dead_code)]
+    pub fn fund_account(&mut self, pubkey: Pubkey, lamports: u64) {
+        let account = self.accounts.entry(pubkey).or_insert_with(|| Account {
+            owner: solana_system_program::id(),
+            ..Default::default()
+        });
+        account.lamports = account.lamports.saturating_add(lamports);
+    }
+
     /// Create a token account.
     ///
-    /// This is a convenience method for creating token accounts.
+    /// This is a convenience method for creating token accounts. The account
+    /// uses the full SPL Token layout in the `Initialized` state with no
+    /// delegate or close authority, so the Token program accepts it as input.
     ///
     /// # Arguments
     ///
@@ -222,34 +1007,274 @@ impl SwapTestContext {
     #[allow(dead_code)]
     pub fn create_token_account(&mut self, owner: Pubkey, mint: Pubkey, amount: u64) -> Pubkey {
         let pubkey = Pubkey::new_unique();
-        let mut data = vec![0; 82]; // Token account size
+        let account = token::create_account_for_token_account(TokenAccount {
+            mint,
+            owner,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        });
+        self.add_rent_exempt_account(pubkey, account);
+        pubkey
+    }
+
+    /// Create a Token-2022 token account with extensions.
+    ///
+    /// The base layout matches [`SwapTestContext::create_token_account`]. When
+    /// `extensions` is non-empty, the account type byte follows the base
+    /// layout and each extension is appended as a type-length-value entry, so
+    /// the buffer is sized exactly as Token-2022 expects.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner of the token account
+    /// * `mint` - The mint address
+    /// * `amount` - Initial token amount
+    /// * `extensions` - `(extension type, value)` pairs, in order
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Pubkey)` - The public key of the new token account
+    /// * `Err(TestContextError)` - If an extension value is too long for its `u16` length
+    #[allow(dead_code)]
+    pub fn create_token2022_account(
+        &mut self,
+        owner: Pubkey,
+        mint: Pubkey,
+        amount: u64,
+        extensions: &[(u16, Vec<u8>)],
+    ) -> Result<Pubkey, TestContextError> {
+        let pubkey = Pubkey::new_unique();
+        let account = token::create_account_for_token_account(TokenAccount {
+            mint,
+            owner,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        });
+        let account = with_extensions(
+            Account { owner: token2022::ID, ..account },
+            ACCOUNT_TYPE_ACCOUNT,
+            extensions,
+        )?;
 
-        // Set owner (offset 32)
-        data[32..64].copy_from_slice(owner.as_ref());
+        self.add_rent_exempt_account(pubkey, account);
+        Ok(pubkey)
+    }
 
-        // Set mint (offset 0)
-        data[0..32].copy_from_slice(mint.as_ref());
+    /// Remember the return data of a successful instruction, clearing any
+    /// left by an earlier one.
+    fn record_return_data(&mut self, result: &InstructionResult) {
+        self.last_return_data =
+            (!result.return_data.is_empty()).then(|| result.return_data.clone());
+    }
 
-        // Set amount (offset 64, little-endian u64)
-        data[64..72].copy_from_slice(&amount.to_le_bytes());
+    /// Describe why an instruction failed, with the program logs collected
+    /// while it ran; see [`execution_failure`].
+    fn failure_message(&self, instruction: &Instruction, result: &InstructionResult) -> String {
+        execution_failure(Some(instruction), result, &self.program_logs(), &self.idl_instructions)
+    }
+}
 
-        let account = Account {
-            lamports: solana_rent::Rent::default().minimum_balance(data.len()),
-            data,
-            owner: spl_token_interface::ID,
-            ..Default::default()
-        };
-        self.add_account(pubkey, account);
-        pubkey
+/// Write an executed instruction and its outcome to the debug trace.
+fn trace_instruction(instruction: &Instruction, result: &InstructionResult) {
+    crate::debug::log(format_args!(
+        "executed {} [{}] with {} accounts: {:?}, {} compute units",
+        instruction.program_id,
+        hex(instruction.data.get(..8).unwrap_or(&instruction.data)),
+        instruction.accounts.len(),
+        result.program_result,
+        result.compute_units_consumed
+    ));
+}
+
+/// Wrap a failure description in the error matching how the program failed.
+///
+/// The runtime reports a panic, an `abort` and a VM fault such as an access
+/// violation all as `ProgramFailedToComplete`, so that error is classified as
+/// an abort; any other error was returned by the program or the runtime.
+fn failure_error(result: &InstructionResult, message: String) -> TestContextError {
+    match result.raw_result {
+        Err(InstructionError::ProgramFailedToComplete) => TestContextError::ProgramAborted(message),
+        _ => TestContextError::ExecutionError(message),
+    }
+}
+
+/// Describe why an instruction failed.
+///
+/// The program's log lines are appended so a failing `require!` can be traced.
+/// An unknown-instruction failure is almost always a discriminator mismatch,
+/// so when the failing `instruction` is known that case also carries the
+/// discriminator that was sent, compared against the instructions in the
+/// program's IDL, to make the cause obvious.
+fn execution_failure(
+    instruction: Option<&Instruction>,
+    result: &InstructionResult,
+    logs: &[String],
+    idl_instructions: &[IdlInstruction],
+) -> String {
+    let mut message = format!("{:?}", result.program_result);
+
+    if matches!(
+        result.raw_result,
+        Err(InstructionError::Custom(ANCHOR_INSTRUCTION_FALLBACK_NOT_FOUND))
+    ) && let Some(instruction) = instruction
+    {
+        let sent = instruction.data.get(..8).unwrap_or(&instruction.data);
+        message.push_str(&format!(": no instruction handler matches discriminator {}", hex(sent)));
+
+        match idl_instructions.iter().find(|idl| idl.discriminator.as_deref() == Some(sent)) {
+            Some(idl) => message.push_str(&format!(
+                ", although the IDL maps it to `{}`; the loaded program binary is likely older \
+                 than the IDL, so rebuild it with `anchor build`",
+                idl.name
+            )),
+            None if !idl_instructions.is_empty() => {
+                let declared: Vec<String> = idl_instructions
+                    .iter()
+                    .map(|idl| match &idl.discriminator {
+                        Some(discriminator) => format!("{} ({})", idl.name, hex(discriminator)),
+                        None => idl.name.clone(),
+                    })
+                    .collect();
+                message.push_str(&format!(
+                    "; the program's IDL declares {}, so the instruction name the tester \
+                     hashes (e.g. `global:make_offer`) does not match any of them",
+                    declared.join(", ")
+                ));
+            }
+            None => message.push_str(
+                "; the instruction name the tester hashes (e.g. `global:make_offer`) does not \
+                 match the program",
+            ),
+        }
+    }
+
+    if !logs.is_empty() {
+        message.push_str("\nProgram logs:");
+        for line in logs {
+            message.push_str("\n  ");
+            message.push_str(line);
+        }
     }
+
+    message
 }
 
 impl Default for SwapTestContext {
     fn default() -> Self {
+        let mut mollusk = Mollusk::default();
+        mollusk.logger.get_or_insert_with(Default::default);
         Self {
-            mollusk: Mollusk::default(),
+            mollusk,
             accounts: HashMap::new(),
             program_id: Pubkey::new_unique(),
+            last_return_data: None,
+            program_build_id: None,
+            idl_instructions: Vec::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_instruction::AccountMeta;
+    use solana_program_pack::Pack;
+    use spl_token_interface::state::Mint;
+
+    /// A system transfer, encoded by hand as the `Transfer` variant (index 2)
+    /// followed by the lamports.
+    fn system_transfer(from: Pubkey, to: Pubkey, lamports: u64) -> Instruction {
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&lamports.to_le_bytes());
+        Instruction::new_with_bytes(
+            solana_system_program::id(),
+            &data,
+            vec![AccountMeta::new(from, true), AccountMeta::new(to, false)],
+        )
+    }
+
+    #[test]
+    fn failed_instruction_leaves_lamports_unchanged() {
+        let mut context = SwapTestContext::default();
+        let from = context.create_funded_account(1_000);
+        let to = context.create_funded_account(1_000);
+        let overdraft = system_transfer(from, to, 5_000);
+
+        assert!(context.execute_instruction_full(&overdraft).is_err());
+        assert_eq!(context.get_account(&from).map(|account| account.lamports), Some(1_000));
+        assert_eq!(context.get_account(&to).map(|account| account.lamports), Some(1_000));
+
+        assert!(context.execute_and_validate(&overdraft, &[]).is_err());
+        assert_eq!(context.get_account(&from).map(|account| account.lamports), Some(1_000));
+        assert_eq!(context.get_account(&to).map(|account| account.lamports), Some(1_000));
+    }
+
+    #[test]
+    fn failed_check_names_account_and_values() {
+        let mut context = SwapTestContext::default();
+        let from = context.create_funded_account(1_000);
+        let to = context.create_funded_account(1_000);
+        let transfer = system_transfer(from, to, 400);
+
+        let err = context
+            .execute_and_validate(
+                &transfer,
+                &[ResultCheck::Success, ResultCheck::Lamports(&to, 1_500)],
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("Validation failed: lamports check on {}: expected 1500, got 1400", to)
+        );
+        assert_eq!(context.get_account(&to).map(|account| account.lamports), Some(1_000));
+    }
+
+    #[test]
+    fn token_program_accepts_synthesized_token_accounts() {
+        let mut context = SwapTestContext::default();
+        token::add_program(context.mollusk_mut());
+
+        let mint = Pubkey::new_unique();
+        context.add_rent_exempt_account(
+            mint,
+            token::create_account_for_mint(Mint {
+                mint_authority: COption::None,
+                supply: 100,
+                decimals: 6,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            }),
+        );
+        let owner = context.create_funded_account(1_000_000_000);
+        let source = context.create_token_account(owner, mint, 100);
+        let destination = context.create_token_account(Pubkey::new_unique(), mint, 0);
+
+        let transfer = spl_token_interface::instruction::transfer_checked(
+            &token::ID,
+            &source,
+            &mint,
+            &destination,
+            &owner,
+            &[],
+            40,
+            6,
+        )
+        .unwrap();
+        context.execute_instruction(&transfer).unwrap();
+
+        let amount = |pubkey: &Pubkey| {
+            TokenAccount::unpack(&context.get_account(pubkey).unwrap().data).unwrap().amount
+        };
+        assert_eq!(amount(&source), 60);
+        assert_eq!(amount(&destination), 40);
+    }
+}
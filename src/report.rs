@@ -0,0 +1,95 @@
+// Copyright (c) The StackClass Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Optional machine-readable report of per-stage results.
+//!
+//! When `STACKCLASS_RESULTS_JSON` is set, every recorded stage is appended to
+//! an in-memory list and the whole list is rewritten to that path, so the file
+//! is complete even if the run stops early.
+
+use serde::Serialize;
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+
+/// Environment variable naming the JSON file to write results to.
+pub const RESULTS_JSON_ENV: &str = "STACKCLASS_RESULTS_JSON";
+
+/// Outcome of a single stage.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageResult {
+    pub stage: String,
+    pub passed: bool,
+    pub duration_ms: u128,
+    pub error: Option<String>,
+    pub compute_units: Option<u64>,
+}
+
+static RESULTS: Mutex<Vec<StageResult>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static COMPUTE_UNITS: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Add compute units consumed by an instruction to the running stage.
+pub fn add_compute_units(units: u64) {
+    COMPUTE_UNITS.with(|total| total.set(Some(total.get().unwrap_or(0).saturating_add(units))));
+}
+
+/// Run a stage and record its outcome.
+///
+/// The stage's own result is returned unchanged, so wrapping a case does not
+/// affect how the harness reports it.
+///
+/// # Arguments
+///
+/// * `stage` - The stage slug
+/// * `run` - The stage body
+pub fn record<F>(stage: &str, run: F) -> Result<(), tester::CaseError>
+where
+    F: FnOnce() -> Result<(), tester::CaseError>,
+{
+    let Some(path) = std::env::var_os(RESULTS_JSON_ENV).map(PathBuf::from) else {
+        return run();
+    };
+
+    COMPUTE_UNITS.with(|total| total.set(None));
+    let started = Instant::now();
+    let outcome = run();
+    let duration = started.elapsed();
+
+    let result = StageResult {
+        stage: stage.to_string(),
+        passed: outcome.is_ok(),
+        duration_ms: duration.as_millis(),
+        error: outcome.as_ref().err().map(|err| err.to_string()),
+        compute_units: COMPUTE_UNITS.with(Cell::get),
+    };
+
+    let mut results = RESULTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    results.push(result);
+    if let Err(err) = write_results(&path, &results) {
+        eprintln!("Failed to write results to {}: {}", path.display(), err);
+    }
+
+    outcome
+}
+
+fn write_results(path: &Path, results: &[StageResult]) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(results).map_err(std::io::Error::other)?;
+    std::fs::write(path, json)
+}
@@ -13,5 +13,5 @@
 // limitations under the License.
 
 pub fn test_cpi_concept(_harness: &tester::Harness) -> Result<(), tester::CaseError> {
-    crate::helpers::run_cpi_checks()
+    crate::helpers::run_cpi_concept_check()
 }
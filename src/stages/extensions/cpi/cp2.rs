@@ -13,5 +13,6 @@
 // limitations under the License.
 
 pub fn test_transfer_checked(_harness: &tester::Harness) -> Result<(), tester::CaseError> {
-    crate::helpers::run_cpi_checks()
+    crate::helpers::run_transfer_checked_check()?;
+    crate::helpers::run_decimals_mismatch_check()
 }
@@ -13,5 +13,5 @@
 // limitations under the License.
 
 pub fn test_mainnet_considerations(_harness: &tester::Harness) -> Result<(), tester::CaseError> {
-    crate::helpers::run_deployment_checks()
+    crate::helpers::run_compute_budget_check(crate::helpers::MAKE_OFFER_COMPUTE_UNIT_LIMIT)
 }
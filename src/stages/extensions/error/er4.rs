@@ -13,5 +13,6 @@
 // limitations under the License.
 
 pub fn test_error_practice(_harness: &tester::Harness) -> Result<(), tester::CaseError> {
-    crate::helpers::run_error_checks()
+    crate::helpers::run_error_checks()?;
+    crate::helpers::run_malformed_instruction_check()
 }
@@ -13,5 +13,6 @@
 // limitations under the License.
 
 pub fn test_offer_validation(_harness: &tester::Harness) -> Result<(), tester::CaseError> {
-    crate::helpers::run_offer_checks()
+    crate::helpers::run_offer_checks()?;
+    crate::helpers::run_duplicate_offer_check()
 }
@@ -13,5 +13,6 @@
 // limitations under the License.
 
 pub fn test_common_vulnerabilities(_harness: &tester::Harness) -> Result<(), tester::CaseError> {
-    crate::helpers::run_security_checks()
+    crate::helpers::run_security_checks()?;
+    crate::helpers::run_wrong_signer_check()
 }
@@ -13,5 +13,6 @@
 // limitations under the License.
 
 pub fn test_receive_tokens(_harness: &tester::Harness) -> Result<(), tester::CaseError> {
-    crate::helpers::run_take_offer_checks()
+    crate::helpers::run_take_offer_checks()?;
+    crate::helpers::run_maker_account_init_check()
 }
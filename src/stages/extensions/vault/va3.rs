@@ -13,5 +13,6 @@
 // limitations under the License.
 
 pub fn test_vault_security(_harness: &tester::Harness) -> Result<(), tester::CaseError> {
-    crate::helpers::run_vault_checks()
+    crate::helpers::run_vault_checks()?;
+    crate::helpers::run_vault_authority_check()
 }